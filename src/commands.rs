@@ -22,3 +22,7 @@ mod let_me_out;
 pub use let_me_out::*;
 mod gag_default;
 pub use gag_default::*;
+mod thread;
+pub use thread::*;
+mod key;
+pub use key::*;
@@ -1,31 +1,76 @@
 //! Change a gaggee's gag
 
+use std::sync::Arc;
+
 use poise::structs::Context;
 use serenity::model::user::User;
 
 use crate::types::*;
+use crate::util::*;
 
 /// Change a gaggee's gag
+///
+/// Loosening a tied gag requires the gaggee to consent to you untying them
 #[poise::command(slash_command, guild_only)]
 pub async fn change_gag(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "The gaggee to change the gag for"]
     target: Option<User>,
+    #[description = "Where the gag to change is. Defaults to this channel"]
+    scope: Option<GagScopeKind>,
     #[description = "The mode to change to"]
-    mode: GagModeName
+    #[autocomplete = "crate::util::gag_mode_name_autocomplete"]
+    mode: Option<GagModeName>,
+    #[description = "The percent of words to muffle. Lower lets some words slip through"]
+    #[min = 0] #[max = 100]
+    intensity: Option<u8>,
+    #[description = "If true, muffled words keep their length, syllables, and capitalization"]
+    word_shape: Option<bool>,
+    #[description = "Comma separated filters to stack on the gag, in order (Example: `Stutter,Whisper`)"]
+    #[autocomplete = "crate::util::csv_gag_filter_autocomplete"]
+    filters: Option<String>,
+    #[description = "Comma separated words to never muffle, like safewords or names"]
+    allowed_words: Option<String>
 ) -> Result<(), serenity::Error> {
+    let filters = match filters.as_deref().map(parse_csv_gag_filters).transpose() {
+        Ok(filters)   => filters,
+        Err(bad_name) => {ctx.say(format!("Error! `{bad_name}` isn't a known gag filter")).await?; return Ok(());}
+    };
+    let allowed_words = match allowed_words.as_deref().map(parse_csv_words).transpose() {
+        Ok(allowed_words) => allowed_words,
+        Err(bad_word)     => {ctx.say(format!("Error! `{bad_word}` isn't a single word")).await?; return Ok(());}
+    };
     let target = target.as_ref().unwrap_or(ctx.author());
+    let scope_kind = scope.unwrap_or_default();
+    let Some(scope) = gag_scope_of(&ctx, scope_kind).await? else {return Ok(());};
+    let here = scope_kind.here();
 
     let change = ChangeGag {
-        channel: ctx.channel_id(),
-        mode
+        scope,
+        mode: mode.clone(),
+        intensity: intensity.map(Intensity::new),
+        word_shape,
+        filters: filters.clone(),
+        allowed_words: allowed_words.clone()
     };
 
     let message = match ctx.data().change_gag(target.id, MemberId::from_invoker(&ctx).expect("The /change_gag command to only be invocable in servers."), change) {
-        Ok(old)                                     => format!("Changed {target}'s gag from {old} ({}) to {mode} ({})", old.icon(), mode.icon()),
-        Err(ChangeGagError::NoConsentForGag)        => format!("{target} hasn't consented to you gagging them"),
-        Err(ChangeGagError::NoConsentForMode(mode)) => format!("{target} has consented to you gagging them but not with mode {mode} ({})", mode.icon()),
-        Err(ChangeGagError::WasntGagged)            => format!("{target} wasn't gagged"),
+        Ok(old) => {
+            let mut message = format!("Changed {target}'s gag {here}");
+            if let Some(mode) = mode {message.push_str(&format!("\nMode: {} ({}) -> {mode} ({})", old.mode, old.mode.icon(), mode.icon()));}
+            if let Some(intensity) = intensity {message.push_str(&format!("\nIntensity: {} -> {}", old.intensity, Intensity::new(intensity)));}
+            if let Some(word_shape) = word_shape {message.push_str(&format!("\nWord shape: {} -> {word_shape}", old.word_shape));}
+            if let Some(filters) = filters {message.push_str(&format!("\nFilters: [{}] -> [{}]", format_gag_filters(&old.filters), format_gag_filters(&filters)));}
+            if let Some(allowed_words) = allowed_words {message.push_str(&format!("\nAllowed words: [{}] -> [{}]", format_words(&old.allowed_words), format_words(&allowed_words)));}
+            message
+        },
+        Err(ChangeGagError::NoConsentForGag)            => format!("{target} hasn't consented to you gagging them"),
+        Err(ChangeGagError::NoConsentForMode(mode))     => format!("{target} has consented to you gagging them but not with mode {mode} ({})", mode.icon()),
+        Err(ChangeGagError::NoConsentForFilter(filter)) => format!("{target} has consented to you gagging them but not with filter {filter}"),
+        Err(ChangeGagError::NoConsentForUntie)          => format!("{target}'s gag is tied and they haven't consented to you untying them, so you can't loosen it"),
+        Err(ChangeGagError::CantUntieYourself)          =>         "Your gag is tied so you can't loosen it".to_string(),
+        Err(ChangeGagError::NotKeyholder)               => format!("{target}'s gag is tied and you don't have the key, so you can't loosen it"),
+        Err(ChangeGagError::WasntGagged)                => format!("{target} wasn't gagged {here}"),
     };
 
     ctx.say(message).await?;
@@ -1,15 +1,18 @@
 //! Export, import, and wipe user data.
 
+use std::sync::Arc;
+
 use poise::structs::Context;
 
 use crate::types::*;
+use crate::migrations::{self, FileKind, MigrationError};
 
 /// Export your data
 #[poise::command(slash_command, dm_only)]
 pub async fn export(
-    ctx: Context<'_, State, serenity::Error>
+    ctx: Context<'_, Arc<State>, serenity::Error>
 ) -> Result<(), serenity::Error> {
-    ctx.say(serde_json::to_string(&ctx.data().export(ctx.author().id)).expect("Serialization to never fail")).await?;
+    ctx.say(serde_json::to_string(&migrations::versioned(&ctx.data().export(ctx.author().id))).expect("Serialization to never fail")).await?;
 
     Ok(())
 }
@@ -17,18 +20,19 @@ pub async fn export(
 /// Import your data
 #[poise::command(slash_command, dm_only)]
 pub async fn import(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "The data to import"]
     data: String
 ) -> Result<(), serenity::Error> {
-    let import_result = match serde_json::from_str(&data) {
+    let import_result = match migrations::migrate_str(FileKind::PortableGaggee, &data) {
         Ok(data) => {ctx.data().import(ctx.author().id, data); Ok(())}
-        Err(_) => Err(())
+        Err(e) => Err(e)
     };
 
     ctx.say(match import_result {
-        Ok(()) => "Data imported",
-        Err(()) => "Invalid data"
+        Ok(()) => "Data imported".to_string(),
+        Err(MigrationError::TooNew(version)) => format!("This data is from a newer version of the bot (version {version}) and can't be imported here"),
+        Err(_) => "Invalid data".to_string()
     }).await?;
 
     Ok(())
@@ -37,7 +41,7 @@ pub async fn import(
 /// Wipe ALL your data from this bot. You should use /export first
 #[poise::command(slash_command, dm_only)]
 pub async fn wipe_my_data(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "Set to true to actually do the wipe"]
     yes_im_sure: Option<bool>
 ) -> Result<(), serenity::Error> {
@@ -1,62 +1,205 @@
-//! Applying, removing, and one-time-using [`Gag`]s.
+//! Applying, timing, removing, and one-time-using [`Gag`]s.
+
+use std::sync::Arc;
 
 use poise::structs::Context;
 use poise::CreateReply;
-use serenity::model::{timestamp::Timestamp, user::User};
+use serenity::model::user::User;
 use serenity::builder::CreateMessage;
 
 use crate::types::*;
+use crate::util::*;
+
+/// Gag someone, or change how long their gag lasts
+#[poise::command(slash_command, subcommands("apply", "extend", "shorten"))]
+pub async fn gag(
+    _: Context<'_, Arc<State>, serenity::Error>
+) -> Result<(), serenity::Error> {
+    unreachable!()
+}
 
 /// "Gag" a user (or yourself) so all their (or your) messages get replaced with muffles
 ///
-/// Currently only applies on a per-channel basis
+/// Applies in this channel unless `scope` says otherwise. When several of a user's gags apply, the most specific one is used
 ///
 /// Requres the user to consent to you gagging (and, if you try to, tying) them using any of the `/trust` commands
 ///
 /// You can always ungag yourself but you can't untie yourself without using `/safeword` or `/export` and `/import`
 #[poise::command(slash_command, guild_only)]
-pub async fn gag(
-    ctx: Context<'_, State, serenity::Error>,
+pub async fn apply(
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "The user to gag. Omit to gag yourself"]
     #[autocomplete = "poise::builtins::autocomplete_command"]
     target: Option<User>,
-    #[description = "Minutes to gag them for. Omit to gag forever"]
-    #[autocomplete = "poise::builtins::autocomplete_command"]
-    minutes: Option<u32>,
+    #[description = "Where to gag them. Defaults to this channel"]
+    scope: Option<GagScopeKind>,
+    #[description = "How long to gag them for, like `1h30m`, `2d`, or `<t:1700000000>`. Plain numbers are minutes"]
+    duration: Option<String>,
+    #[description = "Remove the gag after this many of their messages"]
+    #[min = 1]
+    messages: Option<u32>,
+    #[description = "Remove the gag after this many of their words"]
+    #[min = 1]
+    words: Option<u32>,
     #[description = "Optionally \"tie\" the user so they can't ungag themself"]
     tie: Option<bool>,
-    mode: Option<GagModeName>
+    #[description = "If tying them, keep the key so only you, or whoever you give it to, can untie them"]
+    keep_key: Option<bool>,
+    #[description = "The gag to use"]
+    #[autocomplete = "crate::util::gag_mode_name_autocomplete"]
+    mode: Option<GagModeName>,
+    #[description = "The percent of words to muffle. Lower lets some words slip through"]
+    #[min = 0] #[max = 100]
+    intensity: Option<u8>,
+    #[description = "If true, muffled words keep their length, syllables, and capitalization"]
+    word_shape: Option<bool>,
+    #[description = "Comma separated filters to stack on the gag, in order (Example: `Stutter,Whisper`)"]
+    #[autocomplete = "crate::util::csv_gag_filter_autocomplete"]
+    filters: Option<String>,
+    #[description = "Comma separated words to never muffle, like safewords or names"]
+    allowed_words: Option<String>
 ) -> Result<(), serenity::Error> {
+    let filters = match filters.as_deref().map(parse_csv_gag_filters).transpose() {
+        Ok(filters)   => filters,
+        Err(bad_name) => {ctx.say(format!("Error! `{bad_name}` isn't a known gag filter")).await?; return Ok(());}
+    };
+    let allowed_words = match allowed_words.as_deref().map(parse_csv_words).transpose() {
+        Ok(allowed_words) => allowed_words,
+        Err(bad_word)     => {ctx.say(format!("Error! `{bad_word}` isn't a single word")).await?; return Ok(());}
+    };
+    let until = match duration.as_deref().map(|duration| duration.parse::<GagDuration>()?.end(ctx.created_at())).transpose() {
+        Ok(Some(until)) if until <= ctx.created_at() => {ctx.say("Error! That time has already passed").await?; return Ok(());},
+        Ok(until) => until,
+        Err(e)    => {ctx.say(format!("Error! {e}")).await?; return Ok(());}
+    };
     let target = target.as_ref().unwrap_or(ctx.author());
+    let scope_kind = scope.unwrap_or_default();
+    let Some(scope) = gag_scope_of(&ctx, scope_kind).await? else {return Ok(());};
+    let here = scope_kind.here();
 
     let member_id = MemberId::from_invoker(&ctx).expect("The gag command to only be runnable in a guiild");
 
-    let mut gag_config = match ctx.data().gag_defaults.read().expect("No panics").get(&target.id) {
-        Some(x) => x.default_for(member_id),
-        None => Default::default()
-    };
-    GagConfigDiff {tie, mode}.apply(&mut gag_config);
+    let mut gag_config = ctx.data().with_user(target.id, |x| x.gag_defaults.default_for(member_id));
+    GagConfigDiff {tie, mode, intensity: intensity.map(Intensity::new), word_shape, filters, allowed_words}.apply(&mut gag_config);
 
     let gag_result = ctx.data().gag(target.id, member_id, NewGag {
-        channel: ctx.channel_id(),
+        scope,
         gag: Gag {
-            #[allow(clippy::arithmetic_side_effects, reason = "I don't think it can happen.")]
-            until: minutes.map(|minutes| Timestamp::from_unix_timestamp(ctx.created_at().unix_timestamp() + minutes as i64 * 60).expect("Current time + u32::MAX minutes to be a valid time")),
-            config: gag_config
+            until,
+            messages_left: messages,
+            words_left: words,
+            key: keep_key.unwrap_or_default().then_some(GagKey::new(member_id)),
+            config: gag_config.clone()
         }
     });
 
-    let message = match gag_result.map(|()| (minutes, gag_config.tie)) {
-        Ok((None         , false))      => format!("Gagged {target} in this channel with mode {} ({}) forever"                       , gag_config.mode, gag_config.mode.icon()),
-        Ok((None         , true ))      => format!("Gagged and tied {target} in this channel with mode {} ({}) forever"              , gag_config.mode, gag_config.mode.icon()),
-        Ok((Some(1)      , false))      => format!("Gagged {target} in this channel with mode {} ({}) for 1 minute"                  , gag_config.mode, gag_config.mode.icon()),
-        Ok((Some(1)      , true ))      => format!("Gagged and tied {target} in this channel with mode {} ({}) for 1 minute"         , gag_config.mode, gag_config.mode.icon()),
-        Ok((Some(minutes), false))      => format!("Gagged {target} in this channel with mode {} ({}) for {minutes} minutes"         , gag_config.mode, gag_config.mode.icon()),
-        Ok((Some(minutes), true ))      => format!("Gagged and tied {target} in this channel with mode {} ({}) for {minutes} minutes", gag_config.mode, gag_config.mode.icon()),
-        Err(GagError::NoConsentForGag)  => format!("{target} hasn't consented to you gagging them"),
-        Err(GagError::NoConsentForTie)  => format!("{target} hasn't consented to you tying them"),
-        Err(GagError::NoConsentForMode) => format!("{target} has consented to you gagging them but not with mode {} ({})", gag_config.mode, gag_config.mode.icon()),
-        Err(GagError::AlreadyGagged)    => format!("{target} was already gagged in this channel")
+    let intensity = match gag_config.intensity == Intensity::default() {
+        true  => String::new(),
+        false => format!(" at {} intensity", gag_config.intensity)
+    };
+
+    let filters = match gag_config.filters.is_empty() {
+        true  => String::new(),
+        false => format!(" with filters {}", format_gag_filters(&gag_config.filters))
+    };
+
+    let allowed_words = match gag_config.allowed_words.is_empty() {
+        true  => String::new(),
+        false => format!(" allowing {}", format_words(&gag_config.allowed_words))
+    };
+
+    let left = Gag {messages_left: messages, words_left: words, ..Default::default()}.describe_left();
+    let duration = match (until, left) {
+        (None       , None      ) => " forever".to_string(),
+        (None       , Some(left)) => format!(" for {left}"),
+        (Some(until), None      ) => format!(" until {}", format_timestamp(until)),
+        (Some(until), Some(left)) => format!(" until {} or for {left}", format_timestamp(until))
+    };
+
+    let key = match keep_key.unwrap_or_default() {
+        true  => ", and kept the key",
+        false => ""
+    };
+
+    let message = match gag_result.map(|()| gag_config.tie) {
+        Ok(false)                                 => format!("Gagged {target} {here} with mode {} ({}){intensity}{filters}{allowed_words}{duration}"              , gag_config.mode, gag_config.mode.icon()),
+        Ok(true )                                 => format!("Gagged and tied {target} {here} with mode {} ({}){intensity}{filters}{allowed_words}{duration}{key}", gag_config.mode, gag_config.mode.icon()),
+        Err(GagError::NoConsentForGag)            => format!("{target} hasn't consented to you gagging them"),
+        Err(GagError::NoConsentForTie)            => format!("{target} hasn't consented to you tying them"),
+        Err(GagError::NoConsentForMode)           => format!("{target} has consented to you gagging them but not with mode {} ({})", gag_config.mode, gag_config.mode.icon()),
+        Err(GagError::NoConsentForFilter(filter)) => format!("{target} has consented to you gagging them but not with filter {filter}"),
+        Err(GagError::AlreadyGagged)              => format!("{target} was already gagged {here}"),
+        Err(GagError::KeyWithoutTie)              =>         "Error! You can only keep the key of a tied gag".to_string(),
+        Err(GagError::CantKeepOwnKey)             =>         "Error! You can't keep the key of your own gag".to_string(),
+        Err(GagError::NoConsentForKey)            => format!("{target} hasn't consented to you untying them, so you can't keep the key")
+    };
+
+    ctx.say(message).await?;
+
+    Ok(())
+}
+
+/// Add time to a timed gag
+///
+/// Requires the gaggee to consent to you extending their gags
+#[poise::command(slash_command, guild_only)]
+pub async fn extend(
+    ctx: Context<'_, Arc<State>, serenity::Error>,
+    #[description = "How much time to add, like `1h30m` or `2d`. Plain numbers are minutes"]
+    by: String,
+    #[description = "The gaggee whose gag to extend. Omit to extend your own"]
+    target: Option<User>,
+    #[description = "Where the gag to extend is. Defaults to this channel"]
+    scope: Option<GagScopeKind>
+) -> Result<(), serenity::Error> {
+    change_timer(ctx, by, target, scope, false).await
+}
+
+/// Remove time from a timed gag
+///
+/// Requires the gaggee to consent to you shortening their gags and, if the gag is tied, untying them
+#[poise::command(slash_command, guild_only)]
+pub async fn shorten(
+    ctx: Context<'_, Arc<State>, serenity::Error>,
+    #[description = "How much time to remove, like `1h30m` or `2d`. Plain numbers are minutes"]
+    by: String,
+    #[description = "The gaggee whose gag to shorten. Omit to shorten your own"]
+    target: Option<User>,
+    #[description = "Where the gag to shorten is. Defaults to this channel"]
+    scope: Option<GagScopeKind>
+) -> Result<(), serenity::Error> {
+    change_timer(ctx, by, target, scope, true).await
+}
+
+/// The shared implementation of [`extend`] and [`shorten`].
+async fn change_timer(ctx: Context<'_, Arc<State>, serenity::Error>, by: String, target: Option<User>, scope: Option<GagScopeKind>, shorten: bool) -> Result<(), serenity::Error> {
+    let seconds = match by.parse::<GagDuration>() {
+        Ok(GagDuration::For(seconds)) => match i64::try_from(seconds) {
+            Ok(seconds) => if shorten {seconds.saturating_neg()} else {seconds},
+            Err(_)      => {ctx.say(format!("Error! {}", GagDurationError::TooLong)).await?; return Ok(());}
+        },
+        Ok(GagDuration::Until(_)) => {ctx.say("Error! Use an amount of time like `1h30m`, not a timestamp").await?; return Ok(());},
+        Err(e)                    => {ctx.say(format!("Error! {e}")).await?; return Ok(());}
+    };
+    let target = target.as_ref().unwrap_or(ctx.author());
+    let scope_kind = scope.unwrap_or_default();
+    let Some(scope) = gag_scope_of(&ctx, scope_kind).await? else {return Ok(());};
+    let here = scope_kind.here();
+
+    let result = ctx.data().change_timer(target.id, MemberId::from_invoker(&ctx).expect("The /gag extend and /gag shorten commands to only be invocable in servers."), ChangeTimer {scope, seconds});
+
+    let message = match result {
+        Ok(until) if until <= ctx.created_at()         => format!("{target}'s gag {here} has no time left and will be removed"),
+        Ok(until)                                      => format!("{target}'s gag {here} now lasts until {}", format_timestamp(until)),
+        Err(ChangeTimerError::NoConsentForExtend)      => format!("{target} hasn't consented to you extending their gags"),
+        Err(ChangeTimerError::NoConsentForShorten)     => format!("{target} hasn't consented to you shortening their gags"),
+        Err(ChangeTimerError::NoConsentForMode(mode))  => format!("{target} doesn't consent to you changing the timer of their gag in mode {mode} ({})", mode.icon()),
+        Err(ChangeTimerError::NoConsentForUntie)       => format!("{target}'s gag is tied and they haven't consented to you untying them, so you can't shorten it"),
+        Err(ChangeTimerError::CantUntieYourself)       =>         "Your gag is tied so you can't shorten it".to_string(),
+        Err(ChangeTimerError::NotKeyholder)            => format!("{target}'s gag is tied and you don't have the key, so you can't shorten it"),
+        Err(ChangeTimerError::WasntGagged)             => format!("{target} wasn't gagged {here}"),
+        Err(ChangeTimerError::NoTimer)                 => format!("{target}'s gag {here} lasts forever, so it has no timer to change"),
+        Err(ChangeTimerError::TooLong)                 => format!("Error! {}", GagDurationError::TooLong)
     };
 
     ctx.say(message).await?;
@@ -71,22 +214,29 @@ pub async fn gag(
 /// You can always ungag yourself, but you can't untie yourself without using `/safeword` or `/export` and `/import`
 #[poise::command(slash_command, guild_only)]
 pub async fn ungag(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "The user to ungag. Omit to gag yourself"]
     #[autocomplete = "poise::builtins::autocomplete_command"]
-    target: Option<User>
+    target: Option<User>,
+    #[description = "Where to ungag them. Defaults to this channel"]
+    scope: Option<GagScopeKind>
 ) -> Result<(), serenity::Error> {
     let target = target.as_ref().unwrap_or(ctx.author());
+    let scope_kind = scope.unwrap_or_default();
+    let Some(scope) = gag_scope_of(&ctx, scope_kind).await? else {return Ok(());};
+    let here = scope_kind.here();
 
-    let ungag_result = ctx.data().ungag(target.id, MemberId::from_invoker(&ctx).expect("The ungag command to only be runnable in a guild"), NewUngag {channel: ctx.channel_id()});
+    let ungag_result = ctx.data().ungag(target.id, MemberId::from_invoker(&ctx).expect("The ungag command to only be runnable in a guild"), NewUngag {scope});
 
     let message = match ungag_result {
-        Ok(()) => format!("Ungagged {target} in this channel"),
-        Err(UngagError::NoConsentForUngag)      => format!("{target} hasn't consented to you ungagging them"),
-        Err(UngagError::NoConsentForUntie)      => format!("{target} hasn't consented to you untying them"),
-        Err(UngagError::NoConsentForMode(mode)) => format!("{target} has consented to you ungagging them but not with mode {mode} ({})", mode.icon()),
-        Err(UngagError::CantUntieYourself)      =>         "You can't untie yourself".to_string(),
-        Err(UngagError::WasntGagged)            => format!("{target} wasn't gagged in this channel")
+        Ok(()) => format!("Ungagged {target} {here}"),
+        Err(UngagError::NoConsentForUngag)          => format!("{target} hasn't consented to you ungagging them"),
+        Err(UngagError::NoConsentForUntie)          => format!("{target} hasn't consented to you untying them"),
+        Err(UngagError::NoConsentForMode(mode))     => format!("{target} has consented to you ungagging them but not with mode {mode} ({})", mode.icon()),
+        Err(UngagError::NoConsentForFilter(filter)) => format!("{target} has consented to you ungagging them but not with filter {filter}"),
+        Err(UngagError::CantUntieYourself)          =>         "You can't untie yourself".to_string(),
+        Err(UngagError::NotKeyholder)               => format!("{target}'s gag is tied and you don't have the key"),
+        Err(UngagError::WasntGagged)                => format!("{target} wasn't gagged {here}")
     };
 
     ctx.say(message).await?;
@@ -97,21 +247,28 @@ pub async fn ungag(
 /// Send a message with a gag
 #[poise::command(slash_command)]
 pub async fn gagged(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "The gag to use"]
+    #[autocomplete = "crate::util::gag_mode_name_autocomplete"]
     mode: Option<GagModeName>,
     #[description = "The text to gag"]
     message: String
 ) -> Result<(), serenity::Error> {
-    let mode = mode.unwrap_or_else(|| match ctx.data().gag_defaults.read().expect("No panics").get(&ctx.author().id) {
-        Some(x) => x.default_for(MemberId::from_invoker(&ctx).expect("The /gagged command to only be invocable in servers")),
+    let mut config = match MemberId::from_invoker(&ctx) {
+        Some(member_id) => ctx.data().with_user(ctx.author().id, |x| x.gag_defaults.default_for(member_id)),
         None => Default::default()
-    }.mode);
-    
+    };
+    if let Some(mode) = mode {config.mode = mode;}
+
+    let content = match crate::util::to_gagged_message(&message, &config, ctx.data().preserve_markup_for(ctx.author().id), ctx.author()) {
+        Ok(content) => content,
+        Err(e)      => {ctx.say(format!("Error! Gag mode {} couldn't gag that message: {e}", config.mode)).await?; return Ok(());}
+    };
+
     ctx.channel_id().send_message(
         ctx.http(),
         CreateMessage::new()
-            .content(crate::util::to_gagged_message(&message, mode, ctx.author()))
+            .content(content)
             .allowed_mentions(Default::default())
     ).await?;
 
@@ -1,14 +1,17 @@
 //! Allows setting the default values of gags.
 
+use std::sync::Arc;
+
 use poise::structs::Context;
 use serenity::model::{user::{User}, guild::Member};
 
 use crate::types::*;
+use crate::util::*;
 
 /// Set the default values for gags.
 #[poise::command(slash_command, subcommands("global", "server", "user", "member"))]
 pub async fn gag_default(
-    _: Context<'_, State, serenity::Error>
+    _: Context<'_, Arc<State>, serenity::Error>
 ) -> Result<(), serenity::Error> {
     unreachable!()
 }
@@ -16,17 +19,37 @@ pub async fn gag_default(
 /// Set the global default values for gags.
 #[poise::command(slash_command)]
 pub async fn global(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "The gag to use"]
+    #[autocomplete = "crate::util::gag_mode_name_autocomplete"]
     mode: Option<GagModeName>,
     #[description = "If true, stops you from ungagging yourself"]
-    tie: Option<bool>
+    tie: Option<bool>,
+    #[description = "The percent of words to muffle. Lower lets some words slip through"]
+    #[min = 0] #[max = 100]
+    intensity: Option<u8>,
+    #[description = "If true, muffled words keep their length, syllables, and capitalization"]
+    word_shape: Option<bool>,
+    #[description = "Comma separated filters to stack on the gag, in order (Example: `Stutter,Whisper`)"]
+    #[autocomplete = "crate::util::csv_gag_filter_autocomplete"]
+    filters: Option<String>,
+    #[description = "Comma separated words to never muffle, like safewords or names"]
+    allowed_words: Option<String>
 ) -> Result<(), serenity::Error> {
-    let new_diff = GagConfigDiff {mode, tie};
+    let filters = match filters.as_deref().map(parse_csv_gag_filters).transpose() {
+        Ok(filters)   => filters,
+        Err(bad_name) => {ctx.say(format!("Error! `{bad_name}` isn't a known gag filter")).await?; return Ok(());}
+    };
+    let allowed_words = match allowed_words.as_deref().map(parse_csv_words).transpose() {
+        Ok(allowed_words) => allowed_words,
+        Err(bad_word)     => {ctx.say(format!("Error! `{bad_word}` isn't a single word")).await?; return Ok(());}
+    };
+    let new_diff = GagConfigDiff {mode, tie, intensity: intensity.map(Intensity::new), word_shape, filters, allowed_words};
+    let serialized = serde_json::to_string(&new_diff).expect("Serialization to never fail");
 
-    ctx.data().gag_defaults.write().expect("No panics").entry(ctx.author().id).or_default().global = new_diff;
+    ctx.data().with_user_mut(ctx.author().id, |x| x.gag_defaults.global = new_diff);
 
-    ctx.say(format!("Globally set your gag defaults to `{}`", serde_json::to_string(&new_diff).expect("Serialization to never fail"))).await?;
+    ctx.say(format!("Globally set your gag defaults to `{serialized}`")).await?;
 
     Ok(())
 }
@@ -34,17 +57,37 @@ pub async fn global(
 /// Set the per-server default values for gags
 #[poise::command(slash_command, guild_only)]
 pub async fn server(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "The gag to use"]
+    #[autocomplete = "crate::util::gag_mode_name_autocomplete"]
     mode: Option<GagModeName>,
     #[description = "If true, stop the gaggee from ungagging themself"]
-    tie: Option<bool>
+    tie: Option<bool>,
+    #[description = "The percent of words to muffle. Lower lets some words slip through"]
+    #[min = 0] #[max = 100]
+    intensity: Option<u8>,
+    #[description = "If true, muffled words keep their length, syllables, and capitalization"]
+    word_shape: Option<bool>,
+    #[description = "Comma separated filters to stack on the gag, in order (Example: `Stutter,Whisper`)"]
+    #[autocomplete = "crate::util::csv_gag_filter_autocomplete"]
+    filters: Option<String>,
+    #[description = "Comma separated words to never muffle, like safewords or names"]
+    allowed_words: Option<String>
 ) -> Result<(), serenity::Error> {
-    let new_diff = GagConfigDiff {mode, tie};
+    let filters = match filters.as_deref().map(parse_csv_gag_filters).transpose() {
+        Ok(filters)   => filters,
+        Err(bad_name) => {ctx.say(format!("Error! `{bad_name}` isn't a known gag filter")).await?; return Ok(());}
+    };
+    let allowed_words = match allowed_words.as_deref().map(parse_csv_words).transpose() {
+        Ok(allowed_words) => allowed_words,
+        Err(bad_word)     => {ctx.say(format!("Error! `{bad_word}` isn't a single word")).await?; return Ok(());}
+    };
+    let new_diff = GagConfigDiff {mode, tie, intensity: intensity.map(Intensity::new), word_shape, filters, allowed_words};
+    let serialized = serde_json::to_string(&new_diff).expect("Serialization to never fail");
 
-    ctx.data().gag_defaults.write().expect("No panics").entry(ctx.author().id).or_default().per_guild.insert(ctx.guild_id().expect("The /gag_default server command to only be invocable in servers"), new_diff);
+    ctx.data().with_user_mut(ctx.author().id, |x| x.gag_defaults.per_guild.insert(ctx.guild_id().expect("The /gag_default server command to only be invocable in servers"), new_diff));
 
-    ctx.say(format!("Set your gag defaults for this server to `{}`", serde_json::to_string(&new_diff).expect("Serialization to never fail"))).await?;
+    ctx.say(format!("Set your gag defaults for this server to `{serialized}`")).await?;
 
     Ok(())
 }
@@ -52,19 +95,39 @@ pub async fn server(
 /// Set the per-user default values for gags
 #[poise::command(slash_command)]
 pub async fn user(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "The user to set the default for"]
     user: User,
     #[description = "The gag to use"]
+    #[autocomplete = "crate::util::gag_mode_name_autocomplete"]
     mode: Option<GagModeName>,
     #[description = "If true, stops you from ungagging yourself"]
-    tie: Option<bool>
+    tie: Option<bool>,
+    #[description = "The percent of words to muffle. Lower lets some words slip through"]
+    #[min = 0] #[max = 100]
+    intensity: Option<u8>,
+    #[description = "If true, muffled words keep their length, syllables, and capitalization"]
+    word_shape: Option<bool>,
+    #[description = "Comma separated filters to stack on the gag, in order (Example: `Stutter,Whisper`)"]
+    #[autocomplete = "crate::util::csv_gag_filter_autocomplete"]
+    filters: Option<String>,
+    #[description = "Comma separated words to never muffle, like safewords or names"]
+    allowed_words: Option<String>
 ) -> Result<(), serenity::Error> {
-    let new_diff = GagConfigDiff {mode, tie};
+    let filters = match filters.as_deref().map(parse_csv_gag_filters).transpose() {
+        Ok(filters)   => filters,
+        Err(bad_name) => {ctx.say(format!("Error! `{bad_name}` isn't a known gag filter")).await?; return Ok(());}
+    };
+    let allowed_words = match allowed_words.as_deref().map(parse_csv_words).transpose() {
+        Ok(allowed_words) => allowed_words,
+        Err(bad_word)     => {ctx.say(format!("Error! `{bad_word}` isn't a single word")).await?; return Ok(());}
+    };
+    let new_diff = GagConfigDiff {mode, tie, intensity: intensity.map(Intensity::new), word_shape, filters, allowed_words};
+    let serialized = serde_json::to_string(&new_diff).expect("Serialization to never fail");
 
-    ctx.data().gag_defaults.write().expect("No panics").entry(ctx.author().id).or_default().per_user.insert(user.id, new_diff);
+    ctx.data().with_user_mut(ctx.author().id, |x| x.gag_defaults.per_user.insert(user.id, new_diff));
 
-    ctx.say(format!("Set your gag defaults for {user} to `{}`", serde_json::to_string(&new_diff).expect("Serialization to never fail"))).await?;
+    ctx.say(format!("Set your gag defaults for {user} to `{serialized}`")).await?;
 
     Ok(())
 }
@@ -72,19 +135,39 @@ pub async fn user(
 /// Set the per-member default values for gags
 #[poise::command(slash_command, guild_only)]
 pub async fn member(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "The member to set the default for"]
     member: Member,
     #[description = "The gag to use"]
+    #[autocomplete = "crate::util::gag_mode_name_autocomplete"]
     mode: Option<GagModeName>,
     #[description = "If true, stops you from ungagging yourself"]
-    tie: Option<bool>
+    tie: Option<bool>,
+    #[description = "The percent of words to muffle. Lower lets some words slip through"]
+    #[min = 0] #[max = 100]
+    intensity: Option<u8>,
+    #[description = "If true, muffled words keep their length, syllables, and capitalization"]
+    word_shape: Option<bool>,
+    #[description = "Comma separated filters to stack on the gag, in order (Example: `Stutter,Whisper`)"]
+    #[autocomplete = "crate::util::csv_gag_filter_autocomplete"]
+    filters: Option<String>,
+    #[description = "Comma separated words to never muffle, like safewords or names"]
+    allowed_words: Option<String>
 ) -> Result<(), serenity::Error> {
-    let new_diff = GagConfigDiff {mode, tie};
-
-    ctx.data().gag_defaults.write().expect("No panics").entry(ctx.author().id).or_default().per_member.insert(MemberId::from_member(&member), new_diff);
-
-    ctx.say(format!("Set your gag defaults for {member} to `{}`", serde_json::to_string(&new_diff).expect("Serialization to never fail"))).await?;
+    let filters = match filters.as_deref().map(parse_csv_gag_filters).transpose() {
+        Ok(filters)   => filters,
+        Err(bad_name) => {ctx.say(format!("Error! `{bad_name}` isn't a known gag filter")).await?; return Ok(());}
+    };
+    let allowed_words = match allowed_words.as_deref().map(parse_csv_words).transpose() {
+        Ok(allowed_words) => allowed_words,
+        Err(bad_word)     => {ctx.say(format!("Error! `{bad_word}` isn't a single word")).await?; return Ok(());}
+    };
+    let new_diff = GagConfigDiff {mode, tie, intensity: intensity.map(Intensity::new), word_shape, filters, allowed_words};
+    let serialized = serde_json::to_string(&new_diff).expect("Serialization to never fail");
+
+    ctx.data().with_user_mut(ctx.author().id, |x| x.gag_defaults.per_member.insert(MemberId::from_member(&member), new_diff));
+
+    ctx.say(format!("Set your gag defaults for {member} to `{serialized}`")).await?;
 
     Ok(())
 }
@@ -0,0 +1,50 @@
+//! Handing the keys of tied gags to other people.
+
+use std::sync::Arc;
+
+use poise::structs::Context;
+use serenity::model::user::User;
+
+use crate::types::*;
+use crate::util::*;
+
+/// Manage the keys of tied gags
+#[poise::command(slash_command, subcommands("give"))]
+pub async fn key(
+    _: Context<'_, Arc<State>, serenity::Error>
+) -> Result<(), serenity::Error> {
+    unreachable!()
+}
+
+/// Give the key of a gag you hold the key to
+///
+/// Requires the gaggee to consent to the new keyholder untying them
+#[poise::command(slash_command, guild_only)]
+pub async fn give(
+    ctx: Context<'_, Arc<State>, serenity::Error>,
+    #[description = "The gaggee whose key to give"]
+    target: User,
+    #[description = "Who to give the key to"]
+    to: User,
+    #[description = "Where the gag is. Defaults to this channel"]
+    scope: Option<GagScopeKind>
+) -> Result<(), serenity::Error> {
+    let scope_kind = scope.unwrap_or_default();
+    let Some(scope) = gag_scope_of(&ctx, scope_kind).await? else {return Ok(());};
+    let here = scope_kind.here();
+    let gagger = MemberId::from_invoker(&ctx).expect("The /key give command to only be invocable in servers.");
+
+    let message = match ctx.data().give_key(target.id, gagger, GiveKey {scope, to: MemberId {guild: gagger.guild, user: to.id}}) {
+        Ok(())                                    => format!("Gave {to} the key to {target}'s gag {here}"),
+        Err(GiveKeyError::WasntGagged)            => format!("{target} wasn't gagged {here}"),
+        Err(GiveKeyError::NoKey)                  => format!("{target}'s gag {here} doesn't have a key"),
+        Err(GiveKeyError::NotKeyholder)           => format!("You don't have the key to {target}'s gag {here}"),
+        Err(GiveKeyError::GaggeeCantHoldKey)      => format!("{target} can't hold the key to their own gag"),
+        Err(GiveKeyError::NoConsentForUntie)      => format!("{target} hasn't consented to {to} untying them"),
+        Err(GiveKeyError::NoConsentForMode(mode)) => format!("{target} hasn't consented to {to} untying them in gag mode `{mode}`")
+    };
+
+    ctx.say(message).await?;
+
+    Ok(())
+}
@@ -1,5 +1,7 @@
 //! Lets you ask to be let out.
 
+use std::sync::Arc;
+
 use poise::structs::Context;
 
 use crate::types::*;
@@ -7,7 +9,7 @@ use crate::types::*;
 /// Ask to be let out
 #[poise::command(slash_command)]
 pub async fn let_me_out(
-    ctx: Context<'_, State, serenity::Error>
+    ctx: Context<'_, Arc<State>, serenity::Error>
 ) -> Result<(), serenity::Error> {
     ctx.say(format!("*{} wants to be let out*", ctx.author())).await?;
 
@@ -1,5 +1,7 @@
 //! Prevents unwanted behavior.
 
+use std::sync::Arc;
+
 use poise::structs::Context;
 
 use crate::types::*;
@@ -7,13 +9,47 @@ use crate::types::*;
 /// Set the max length of a message to gag
 #[poise::command(slash_command)]
 pub async fn set_max_message_length_to_gag(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "The max legnth of a message to gag"]
     length: usize
 ) -> Result<(), serenity::Error> {
-    *ctx.data().max_msg_lengths.write().expect("No panics").entry(ctx.author().id).or_insert(default_max_msg_length()) = length;
+    ctx.data().with_user_mut(ctx.author().id, |x| x.max_msg_length = Some(length));
 
     ctx.say(format!("Set your message length limit to {length}")).await?;
 
     Ok(())
 }
+
+/// Set whether to keep mentions, custom emoji, timestamps, links, and code as is when you're gagged
+#[poise::command(slash_command)]
+pub async fn set_preserve_markup(
+    ctx: Context<'_, Arc<State>, serenity::Error>,
+    #[description = "If true, mentions, custom emoji, timestamps, links, and code don't get gagged"]
+    enabled: bool
+) -> Result<(), serenity::Error> {
+    ctx.data().with_user_mut(ctx.author().id, |x| x.preserve_markup = Some(enabled));
+
+    ctx.say(match enabled {
+        true  => "Mentions, custom emoji, timestamps, links, and code will be kept when you're gagged",
+        false => "Mentions, custom emoji, timestamps, links, and code will be gagged along with everything else"
+    }).await?;
+
+    Ok(())
+}
+
+/// Set whether to post a message when one of your gags expires or runs out of messages or words
+#[poise::command(slash_command)]
+pub async fn set_announce_gag_expiry(
+    ctx: Context<'_, Arc<State>, serenity::Error>,
+    #[description = "If true, a message is posted when one of your gags expires or runs out"]
+    enabled: bool
+) -> Result<(), serenity::Error> {
+    ctx.data().with_user_mut(ctx.author().id, |x| x.announce_expiry = enabled);
+
+    ctx.say(match enabled {
+        true  => "A message will be posted when one of your gags expires or runs out",
+        false => "Your gags will expire silently"
+    }).await?;
+
+    Ok(())
+}
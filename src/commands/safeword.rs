@@ -1,8 +1,11 @@
 //! Enabling and disabling parts of the [`Safeword`] system.
 
+use std::sync::Arc;
+
 use poise::structs::Context;
 
 use crate::types::*;
+use crate::util::*;
 
 /// Activate a safeword to temporarily ungag yourself globally, per-server, and per-channel
 ///
@@ -11,18 +14,17 @@ use crate::types::*;
 /// Gags with a time limit won't have their time limit extended
 #[poise::command(slash_command)]
 pub async fn safeword(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "Where to apply the safeword for"]
     r#where: SafewordLocation
 ) -> Result<(), serenity::Error> {
-    let (safeword_result, relevant_safewords) = {
-        let mut safeword_lock = ctx.data().safewords.write().expect("No panics");
-        let safewords = safeword_lock.entry(ctx.author().id).or_default();
-        let safeword_result = safewords.add_safeword(r#where, ctx.channel_id(), ctx.guild_id());
-        let relevant_safewords = safewords.get_relevant_safewords(ctx.channel_id(), ctx.guild_id());
+    let location = gag_location(ctx, ctx.channel_id(), ctx.guild_id()).await;
+    let (safeword_result, relevant_safewords) = ctx.data().with_user_mut(ctx.author().id, |x| {
+        let safeword_result = x.safewords.add_safeword(r#where, ctx.channel_id(), ctx.guild_id());
+        let relevant_safewords = x.safewords.get_relevant_safewords(location);
 
         (safeword_result, relevant_safewords)
-    };
+    });
 
     let mut message = match (r#where, safeword_result) {
         (SafewordLocation::Global , Ok (true)                      ) => "Enabled the global safeword",
@@ -45,18 +47,17 @@ pub async fn safeword(
 /// Deactivates a safeword to re-apply all gags that haven't yet expired
 #[poise::command(slash_command)]
 pub async fn unsafeword(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "Where to revoke the safeword for"]
     r#where: SafewordLocation
 ) -> Result<(), serenity::Error> {
-    let (unsafeword_result, relevant_safewords) = {
-        let mut safeword_lock = ctx.data().safewords.write().expect("No panics");
-        let safewords = safeword_lock.entry(ctx.author().id).or_default();
-        let unsafeword_result = safewords.remove_safeword(r#where, ctx.channel_id(), ctx.guild_id());
-        let relevant_safewords = safewords.get_relevant_safewords(ctx.channel_id(), ctx.guild_id());
+    let location = gag_location(ctx, ctx.channel_id(), ctx.guild_id()).await;
+    let (unsafeword_result, relevant_safewords) = ctx.data().with_user_mut(ctx.author().id, |x| {
+        let unsafeword_result = x.safewords.remove_safeword(r#where, ctx.channel_id(), ctx.guild_id());
+        let relevant_safewords = x.safewords.get_relevant_safewords(location);
 
         (unsafeword_result, relevant_safewords)
-    };
+    });
 
     let mut message = match (r#where, unsafeword_result) {
         (SafewordLocation::Global , Ok (true)                      ) => "Disabled the global safeword",
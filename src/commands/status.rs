@@ -1,37 +1,59 @@
 //! Getting the state of a user in a channel.
 
+use std::sync::Arc;
+
 use poise::structs::Context;
+use serenity::model::mention::Mentionable;
 use serenity::model::user::User;
 
 use crate::types::*;
+use crate::util::*;
 
 #[poise::command(slash_command, guild_only)]
 pub async fn status(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "The user to get the status of. Omit to get your own"]
     #[autocomplete = "poise::builtins::autocomplete_command"]
-    target: Option<User>
+    target: Option<User>,
+    #[description = "Only show the gag applied here. Omit to show the gag that applies in this channel"]
+    scope: Option<GagScopeKind>
 ) -> Result<(), serenity::Error> {
     let target = target.as_ref().unwrap_or(ctx.author());
+    let location = gag_location(ctx, ctx.channel_id(), ctx.guild_id()).await;
+    let scope = match scope {
+        Some(kind) => match gag_scope_of(&ctx, kind).await? {
+            Some(scope) => Some(scope),
+            None => return Ok(())
+        },
+        None => None
+    };
 
-    let message = {
-        let lock = ctx.data().gags.read().expect("No panics");
-        let gag = lock.get(&target.id)
-            .and_then(|gags| gags.get(&ctx.channel_id()))
-            .filter(|gag| gag.until.is_none_or(|until| ctx.created_at() <= until));
+    let message = ctx.data().with_user(target.id, |x| {
+        let gag = match scope {
+            Some(scope) => x.gags.get(&scope).filter(|gag| gag.until.is_none_or(|until| ctx.created_at() <= until)).map(|gag| (scope, gag)),
+            None => x.gag_at(location, ctx.created_at())
+        };
 
-        let mut message = match gag {
-            Some(gag) => format!("{target} has the following gag applied in this channel: `{}`", serde_json::to_string(gag).expect("Serialization to never fail")),
-            None => format!("{target} doesn't have a gag applied in this channel")
+        let mut message = match (gag, scope) {
+            (Some((scope, gag)), _) => format!("{target} has the following gag applied {}: `{}`", location.here(scope), serde_json::to_string(gag).expect("Serialization to never fail")),
+            (None, Some(scope)    ) => format!("{target} doesn't have a gag applied {}", location.here(scope)),
+            (None, None           ) => format!("{target} doesn't have a gag that applies in this channel")
         };
-        if let Some(safewords) = ctx.data().safewords.read().expect("No panics").get(&target.id) {
-            let locations = safewords.get_relevant_safewords(ctx.channel_id(), ctx.guild_id());
-            if !locations.is_empty() {
-                message.push_str(&format!("\n{target} has the following relevant safewords enabled: {locations:?}"));
-            }
+        if let Some((_, gag)) = gag && let Some(left) = gag.describe_left() {
+            message.push_str(&format!("\n{target}'s gag will be removed after {left}"));
+        }
+        if let Some((_, gag)) = gag && let Some(key) = &gag.key {
+            message.push_str(&format!("\n{target}'s gag is tied and {} has the key", key.holder.user.mention()));
+        }
+        if let Some((_, gag)) = gag && !gag.config.allowed_words.is_empty() {
+            message.push_str(&format!("\n{target} can say these words through their gag: {}", format_words(&gag.config.allowed_words)));
+        }
+        let locations = x.safewords.get_relevant_safewords(location);
+        if !locations.is_empty() {
+            message.push_str(&format!("\n{target} has the following relevant safewords enabled: {locations:?}"));
         }
         message
-    };
+    });
 
     ctx.say(message).await?;
 
@@ -1,5 +1,7 @@
 //! Lets you RP struggling against the ropes.
 
+use std::sync::Arc;
+
 use poise::structs::Context;
 use rand::prelude::*;
 
@@ -8,7 +10,7 @@ use crate::types::*;
 /// Struggle against the ropes
 #[poise::command(slash_command)]
 pub async fn struggle(
-    ctx: Context<'_, State, serenity::Error>
+    ctx: Context<'_, Arc<State>, serenity::Error>
 ) -> Result<(), serenity::Error> {
     let responses = [
         format!("*{} struggled against the ropes*", ctx.author()),
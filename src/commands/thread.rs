@@ -0,0 +1,32 @@
+//! Choosing whether threads and forum posts get the gags of the channel they're in.
+
+use std::sync::Arc;
+
+use poise::structs::Context;
+
+use crate::types::*;
+use crate::util::*;
+
+/// Set whether your gags on this thread's channel apply in this thread
+///
+/// Gags on the thread itself, its category, the server, and global gags still apply, and so do tied gags on the thread's channel
+#[poise::command(slash_command, guild_only)]
+pub async fn set_thread_inherits_gags(
+    ctx: Context<'_, Arc<State>, serenity::Error>,
+    #[description = "If false, your gags on this thread's channel don't apply in this thread"]
+    enabled: bool
+) -> Result<(), serenity::Error> {
+    let location = gag_location(ctx, ctx.channel_id(), ctx.guild_id()).await;
+    let caller = MemberId::from_invoker(&ctx).expect("The /set_thread_inherits_gags command to only be invocable in servers.");
+
+    let message = match ctx.data().try_with_user_mut(ctx.author().id, |x| x.set_thread_inherits_gags(ctx.author().id, caller, location, enabled)) {
+        Ok(()) if enabled                         => "Your gags on this thread's channel now apply in this thread",
+        Ok(())                                    => "Your gags on this thread's channel no longer apply in this thread",
+        Err(ThreadOptOutError::NotInThread)       => "Error! This isn't a thread or forum post",
+        Err(ThreadOptOutError::NoConsentForUntie) => "Your gag on this thread's channel is tied, so it still applies in this thread"
+    };
+
+    ctx.say(message).await?;
+
+    Ok(())
+}
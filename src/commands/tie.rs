@@ -1,25 +1,38 @@
 //! Tying and untying gaggees without having un ungag and regag them.
 
+use std::sync::Arc;
+
 use poise::structs::Context;
 use serenity::model::user::User;
 
 use crate::types::*;
+use crate::util::*;
 
 /// Tie a gaggee
 #[poise::command(slash_command, guild_only)]
 pub async fn tie(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "The gaggee to tie"]
-    target: Option<User>
+    target: Option<User>,
+    #[description = "Where the gag to tie is. Defaults to this channel"]
+    scope: Option<GagScopeKind>,
+    #[description = "Keep the key so only you, or whoever you give it to, can untie them"]
+    keep_key: Option<bool>
 ) -> Result<(), serenity::Error> {
     let target = target.as_ref().unwrap_or(ctx.author());
+    let scope_kind = scope.unwrap_or_default();
+    let Some(scope) = gag_scope_of(&ctx, scope_kind).await? else {return Ok(());};
+    let here = scope_kind.here();
 
-    let message = match ctx.data().tie(target.id, MemberId::from_invoker(&ctx).expect("The /tie command to only be invocable in servers."), NewTie {channel: ctx.channel_id()}) {
-        Ok(())                                => format!("Tied {target}"),
-        Err(TieError::WasntGagged)            => format!("{target} wasn't gagged"),
+    let message = match ctx.data().tie(target.id, MemberId::from_invoker(&ctx).expect("The /tie command to only be invocable in servers."), NewTie {scope, keep_key: keep_key.unwrap_or_default()}) {
+        Ok(()) if keep_key == Some(true)      => format!("Tied {target} {here} and kept the key"),
+        Ok(())                                => format!("Tied {target} {here}"),
+        Err(TieError::WasntGagged)            => format!("{target} wasn't gagged {here}"),
         Err(TieError::AlreadyTied)            => format!("{target} was already tied"),
         Err(TieError::NoConsentForTie)        => format!("{target} doesn't consent to you tying them"),
-        Err(TieError::NoConsentForMode(mode)) => format!("{target} doesn't consent to you tying them in gag mode `{mode}`")
+        Err(TieError::NoConsentForMode(mode)) => format!("{target} doesn't consent to you tying them in gag mode `{mode}`"),
+        Err(TieError::CantKeepOwnKey)         =>         "Error! You can't keep the key of your own gag".to_string(),
+        Err(TieError::NoConsentForKey)        => format!("{target} hasn't consented to you untying them, so you can't keep the key")
     };
 
     ctx.say(message).await?;
@@ -30,18 +43,24 @@ pub async fn tie(
 /// Untie a gaggee, leaving the gag
 #[poise::command(slash_command, guild_only)]
 pub async fn untie(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "The gaggee to untie"]
-    target: Option<User>
+    target: Option<User>,
+    #[description = "Where the gag to untie is. Defaults to this channel"]
+    scope: Option<GagScopeKind>
 ) -> Result<(), serenity::Error> {
     let target = target.as_ref().unwrap_or(ctx.author());
+    let scope_kind = scope.unwrap_or_default();
+    let Some(scope) = gag_scope_of(&ctx, scope_kind).await? else {return Ok(());};
+    let here = scope_kind.here();
 
-    let message = match ctx.data().untie(target.id, MemberId::from_invoker(&ctx).expect("The /tie command to only be invocable in servers."), NewUntie {channel: ctx.channel_id()}) {
-        Ok(())                                  => format!("Untied {target}"),
-        Err(UntieError::WasntGagged)            => format!("{target} wasn't gagged"),
+    let message = match ctx.data().untie(target.id, MemberId::from_invoker(&ctx).expect("The /tie command to only be invocable in servers."), NewUntie {scope}) {
+        Ok(())                                  => format!("Untied {target} {here}"),
+        Err(UntieError::WasntGagged)            => format!("{target} wasn't gagged {here}"),
         Err(UntieError::WasntTied)              => format!("{target} wasn't tied"),
         Err(UntieError::NoConsentForUntie)      => format!("{target} doesn't consent to you untying them"),
         Err(UntieError::CantUntieYourself)      =>         "You can't untie yourself".to_string(),
+        Err(UntieError::NotKeyholder)           => format!("{target}'s gag is tied and you don't have the key"),
         Err(UntieError::NoConsentForMode(mode)) => format!("{target} doesn't consent to you untying them in gag mode `{mode}`"),
     };
 
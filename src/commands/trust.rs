@@ -1,5 +1,7 @@
 //! Setting [`Trust`] levels.
 
+use std::sync::Arc;
+
 use poise::structs::Context;
 use serenity::model::{guild::Member, user::User};
 
@@ -9,7 +11,7 @@ use crate::util::*;
 /// Sets the trust levels for the current server, a user, or a member
 #[poise::command(slash_command, subcommands("global", "server", "user", "member", "query"))]
 pub async fn trust(
-    _ctx: Context<'_, State, serenity::Error>
+    _ctx: Context<'_, Arc<State>, serenity::Error>
 ) -> Result<(), serenity::Error> {
     unreachable!()
 }
@@ -17,7 +19,7 @@ pub async fn trust(
 /// Set trust config for everyone in any server
 #[poise::command(slash_command, guild_only)]
 pub async fn global(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "Trust everyone in any server to gag you"]
     gag: Option<bool>,
     #[description = "Trust everyone in any server to ungag you"]
@@ -26,10 +28,22 @@ pub async fn global(
     tie: Option<bool>,
     #[description = "Trust everyone in any server to untie you"]
     untie: Option<bool>,
+    #[description = "Trust everyone in any server to add time to your timed gags"]
+    extend: Option<bool>,
+    #[description = "Trust everyone in any server to remove time from your timed gags"]
+    shorten: Option<bool>,
     #[description = "Trust everyone in any server to use these gag modes"]
     #[autocomplete = "crate::util::csv_gag_mode_name_autocomplete"]
-    gag_modes: Option<String>
+    gag_modes: Option<String>,
+    #[description = "Trust everyone in any server to use these gag filters"]
+    #[autocomplete = "crate::util::csv_gag_filter_autocomplete"]
+    gag_filters: Option<String>
 ) -> Result<(), serenity::Error> {
+    let gag_filters = match parse_csv_gag_filters(gag_filters.as_deref().unwrap_or_default()) {
+        Ok(gag_filters) => gag_filters,
+        Err(bad_name)   => {ctx.say(format!("Error! `{bad_name}` isn't a known gag filter")).await?; return Ok(());}
+    };
+
     match parse_csv_gag_modes(gag_modes.as_deref()) {
         Ok(gag_modes) => {
             let trust = Trust {
@@ -37,11 +51,14 @@ pub async fn global(
                 ungag: ungag.unwrap_or_default(),
                 tie  : tie  .unwrap_or_default(),
                 untie: untie.unwrap_or_default(),
-                gag_modes
+                extend: extend.unwrap_or_default(),
+                shorten: shorten.unwrap_or_default(),
+                gag_modes,
+                gag_filters
             };
 
             let serialized = serde_json::to_string(&trust).expect("Serialization to never fail");
-            ctx.data().trusts.write().expect("No panics").entry(ctx.author().id).or_default().global = trust;
+            ctx.data().with_user_mut(ctx.author().id, |x| x.trusts.global = trust);
 
             ctx.say(format!("Overwrote your global trust to `{serialized}`")).await?;
         },
@@ -54,7 +71,7 @@ pub async fn global(
 /// Set trust config for everyone this server
 #[poise::command(slash_command, guild_only)]
 pub async fn server(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "Trust everyone in this server to gag you"]
     gag: Option<bool>,
     #[description = "Trust everyone in this server to ungag you"]
@@ -63,23 +80,40 @@ pub async fn server(
     tie: Option<bool>,
     #[description = "Trust everyone in this server to untie you"]
     untie: Option<bool>,
+    #[description = "Trust everyone in this server to add time to your timed gags"]
+    extend: Option<bool>,
+    #[description = "Trust everyone in this server to remove time from your timed gags"]
+    shorten: Option<bool>,
     #[description = "Allow everyone in this server to use these gag modes"]
     #[autocomplete = "crate::util::csv_gag_mode_name_autocomplete"]
     allow_gag_modes: Option<String>,
     #[description = "Disallow everyone in this server from using these gag modes"]
     #[autocomplete = "crate::util::csv_gag_mode_name_autocomplete"]
-    disallow_gag_modes: Option<String>
+    disallow_gag_modes: Option<String>,
+    #[description = "Allow everyone in this server to use these gag filters"]
+    #[autocomplete = "crate::util::csv_gag_filter_autocomplete"]
+    allow_gag_filters: Option<String>,
+    #[description = "Disallow everyone in this server from using these gag filters"]
+    #[autocomplete = "crate::util::csv_gag_filter_autocomplete"]
+    disallow_gag_filters: Option<String>
 ) -> Result<(), serenity::Error> {
+    let (allow_gag_filters, disallow_gag_filters) = match parse_trust_gag_filters(allow_gag_filters.as_deref(), disallow_gag_filters.as_deref()) {
+        Ok(filters) => filters,
+        Err(e)      => {ctx.say(e).await?; return Ok(());}
+    };
+
     match (parse_csv_gag_modes(allow_gag_modes.as_deref()), parse_csv_gag_modes(disallow_gag_modes.as_deref())) {
         (Ok(allow_gag_modes), Ok(disallow_gag_modes)) => {
             let diff = TrustDiff {
-                gag, ungag, tie, untie,
+                gag, ungag, tie, untie, extend, shorten,
                 allow_gag_modes,
-                disallow_gag_modes
+                disallow_gag_modes,
+                allow_gag_filters,
+                disallow_gag_filters
             };
 
             let serialized = serde_json::to_string(&diff).expect("Serialization to never fail");
-            let overwrote = ctx.data().trusts.write().expect("No panics").entry(ctx.author().id).or_default().per_guild.insert(ctx.guild_id().expect("The trust server command to only be invocable in servers"), diff).is_some();
+            let overwrote = ctx.data().with_user_mut(ctx.author().id, |x| x.trusts.per_guild.insert(ctx.guild_id().expect("The trust server command to only be invocable in servers"), diff)).is_some();
 
             ctx.say(match overwrote {
                 true  => format!("Overwrote this server's trust with `{serialized}`"),
@@ -95,7 +129,7 @@ pub async fn server(
 /// Set trust config for a user in any server
 #[poise::command(slash_command, guild_only)]
 pub async fn user(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "The user to set the trust config for in any server"]
     user: User,
     #[description = "Trust them to gag you in any server"]
@@ -106,34 +140,51 @@ pub async fn user(
     tie: Option<bool>,
     #[description = "Trust them to untie you in any server"]
     untie: Option<bool>,
+    #[description = "Trust them to add time to your timed gags in any server"]
+    extend: Option<bool>,
+    #[description = "Trust them to remove time from your timed gags in any server"]
+    shorten: Option<bool>,
     #[description = "Allow them to use these gag modes in any server"]
     #[autocomplete = "crate::util::csv_gag_mode_name_autocomplete"]
     allow_gag_modes: Option<String>,
     #[description = "Disallow them from using these gag modes in any server"]
     #[autocomplete = "crate::util::csv_gag_mode_name_autocomplete"]
-    disallow_gag_modes: Option<String>
+    disallow_gag_modes: Option<String>,
+    #[description = "Allow them to use these gag filters in any server"]
+    #[autocomplete = "crate::util::csv_gag_filter_autocomplete"]
+    allow_gag_filters: Option<String>,
+    #[description = "Disallow them from using these gag filters in any server"]
+    #[autocomplete = "crate::util::csv_gag_filter_autocomplete"]
+    disallow_gag_filters: Option<String>
 ) -> Result<(), serenity::Error> {
     if ctx.author().id == user.id {
         ctx.say("You can't overwrite your trust for yourself. You can always do anything to yourself except for untying").await?;
         return Ok(());
     }
 
+    let (allow_gag_filters, disallow_gag_filters) = match parse_trust_gag_filters(allow_gag_filters.as_deref(), disallow_gag_filters.as_deref()) {
+        Ok(filters) => filters,
+        Err(e)      => {ctx.say(e).await?; return Ok(());}
+    };
+
     match (parse_csv_gag_modes(allow_gag_modes.as_deref()), parse_csv_gag_modes(disallow_gag_modes.as_deref())) {
         (Ok(allow_gag_modes), Ok(disallow_gag_modes)) => {
             let diff = TrustDiff {
-                gag, ungag, tie, untie,
+                gag, ungag, tie, untie, extend, shorten,
                 allow_gag_modes,
-                disallow_gag_modes
+                disallow_gag_modes,
+                allow_gag_filters,
+                disallow_gag_filters
             };
 
             let serialized = serde_json::to_string(&diff).expect("Serialization to never fail");
-            let overwrote = ctx.data().trusts.write().expect("No panics").entry(ctx.author().id).or_default().per_user.insert(user.id, diff).is_some();
+            let overwrote = ctx.data().with_user_mut(ctx.author().id, |x| x.trusts.per_user.insert(user.id, diff)).is_some();
             let sum = ctx.data().trust_for(ctx.author().id, MemberId {user: user.id, guild: ctx.guild_id().expect("The /trust member command to only be invokable in servers")});
             let sum_message = serde_json::to_string(&sum).expect("Serialization to never fail");
 
-            let warning = match (sum.gag_modes.len(), sum.gag || sum.ungag || sum.tie || sum.untie) {
+            let warning = match (sum.gag_modes.len(), sum.gag || sum.ungag || sum.tie || sum.untie || sum.extend || sum.shorten) {
                 (0  , true ) => format!("\nWarning: Your sum trust for {user} has actions but no modes. If this is an error and you don't intend to use other trust layers to manage their gag modes, re-run this command with `allow_gag_modes` set to a comma separated list of gag modes (Example: `Gag,Dog`)"),
-                (1.., false) => format!("\nWarning: Your sum trust for {user} has modes but no actions. If this is an error and you don't intend to use other trust layers to manage their actions, re-run this command with any of `gag`, `ungag`, `tie`, and/or `untie` set to `True`"),
+                (1.., false) => format!("\nWarning: Your sum trust for {user} has modes but no actions. If this is an error and you don't intend to use other trust layers to manage their actions, re-run this command with any of `gag`, `ungag`, `tie`, `untie`, `extend`, and/or `shorten` set to `True`"),
                 _ => "".to_string()
             };
 
@@ -151,7 +202,7 @@ pub async fn user(
 /// Set trust config for a user in this server
 #[poise::command(slash_command, guild_only)]
 pub async fn member(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "The user to set the trust config for in this server"]
     member: Member,
     #[description = "Trust them to gag you in this server"]
@@ -162,34 +213,51 @@ pub async fn member(
     tie: Option<bool>,
     #[description = "Trust them to untie you in this server"]
     untie: Option<bool>,
+    #[description = "Trust them to add time to your timed gags in this server"]
+    extend: Option<bool>,
+    #[description = "Trust them to remove time from your timed gags in this server"]
+    shorten: Option<bool>,
     #[description = "Allow them to use these gag modes in this server"]
     #[autocomplete = "crate::util::csv_gag_mode_name_autocomplete"]
     allow_gag_modes: Option<String>,
     #[description = "Disallow them from using these gag modes in this server"]
     #[autocomplete = "crate::util::csv_gag_mode_name_autocomplete"]
-    disallow_gag_modes: Option<String>
+    disallow_gag_modes: Option<String>,
+    #[description = "Allow them to use these gag filters in this server"]
+    #[autocomplete = "crate::util::csv_gag_filter_autocomplete"]
+    allow_gag_filters: Option<String>,
+    #[description = "Disallow them from using these gag filters in this server"]
+    #[autocomplete = "crate::util::csv_gag_filter_autocomplete"]
+    disallow_gag_filters: Option<String>
 ) -> Result<(), serenity::Error> {
     if ctx.author().id == member.user.id {
         ctx.say("You can't overwrite your trust for yourself. You can always do anything to yourself except for untying").await?;
         return Ok(());
     }
 
+    let (allow_gag_filters, disallow_gag_filters) = match parse_trust_gag_filters(allow_gag_filters.as_deref(), disallow_gag_filters.as_deref()) {
+        Ok(filters) => filters,
+        Err(e)      => {ctx.say(e).await?; return Ok(());}
+    };
+
     match (parse_csv_gag_modes(allow_gag_modes.as_deref()), parse_csv_gag_modes(disallow_gag_modes.as_deref())) {
         (Ok(allow_gag_modes), Ok(disallow_gag_modes)) => {
             let diff = TrustDiff {
-                gag, ungag, tie, untie,
+                gag, ungag, tie, untie, extend, shorten,
                 allow_gag_modes,
-                disallow_gag_modes
+                disallow_gag_modes,
+                allow_gag_filters,
+                disallow_gag_filters
             };
 
             let serialized = serde_json::to_string(&diff).expect("Serialization to never fail");
-            let overwrote = ctx.data().trusts.write().expect("No panics").entry(ctx.author().id).or_default().per_member.insert(MemberId::from_member(&member), diff).is_some();
+            let overwrote = ctx.data().with_user_mut(ctx.author().id, |x| x.trusts.per_member.insert(MemberId::from_member(&member), diff)).is_some();
             let sum = ctx.data().trust_for(ctx.author().id, MemberId::from_member(&member));
             let sum_message = serde_json::to_string(&sum).expect("Serialization to never fail");
 
-            let warning = match (sum.gag_modes.len(), sum.gag || sum.ungag || sum.tie || sum.untie) {
+            let warning = match (sum.gag_modes.len(), sum.gag || sum.ungag || sum.tie || sum.untie || sum.extend || sum.shorten) {
                 (0  , true ) => format!("\nWarning: Your sum trust for {member} has actions but no modes. If this is an error and you don't intend to use other trust layers to manage their gag modes, re-run this command with `allow_gag_modes` set to a comma separated list of gag modes (Example: `Gag,Dog`)"),
-                (1.., false) => format!("\nWarning: Your sum trust for {member} has modes but no actions. If this is an error and you don't intend to use other trust layers to manage their actions, re-run this command with any of `gag`, `ungag`, `tie`, and/or `untie` set to `True`"),
+                (1.., false) => format!("\nWarning: Your sum trust for {member} has modes but no actions. If this is an error and you don't intend to use other trust layers to manage their actions, re-run this command with any of `gag`, `ungag`, `tie`, `untie`, `extend`, and/or `shorten` set to `True`"),
                 _ => "".to_string()
             };
 
@@ -207,7 +275,7 @@ pub async fn member(
 /// Get the trusts between you and a user in this server
 #[poise::command(slash_command, guild_only)]
 pub async fn query(
-    ctx: Context<'_, State, serenity::Error>,
+    ctx: Context<'_, Arc<State>, serenity::Error>,
     #[description = "The member to get the trust details of and for"]
     member: Member
 ) -> Result<(), serenity::Error> {
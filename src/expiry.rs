@@ -0,0 +1,53 @@
+//! Removing timed gags when they expire instead of waiting for someone to run a command.
+
+use std::sync::Arc;
+
+use serenity::builder::CreateMessage;
+use serenity::http::Http;
+use serenity::model::mention::Mentionable;
+use serenity::model::timestamp::Timestamp;
+
+use crate::types::*;
+
+/// Announces each [`ExpiredGag`] whose gaggee has [`UserState::announce_expiry`] on.
+///
+/// Channel gags are announced with "X's gag has expired" in their channel. Other gags don't have a channel, so the gaggee is DMed instead.
+///
+/// Failures, like not being allowed to talk in the channel, are logged to STDERR.
+pub async fn announce(http: &Http, expired: Vec<ExpiredGag>) {
+    for expired in expired.into_iter().filter(|expired| expired.announce) {
+        let result = match expired.scope {
+            GagScope::Channel(channel) => channel.send_message(http, CreateMessage::new()
+                .content(format!("{}'s gag has expired", expired.user.mention()))
+                .allowed_mentions(Default::default())
+            ).await,
+            GagScope::Category(_) => expired.user.direct_message(http, CreateMessage::new().content("Your category-wide gag has expired")).await,
+            GagScope::Server  (_) => expired.user.direct_message(http, CreateMessage::new().content("Your server-wide gag has expired"  )).await,
+            GagScope::Global      => expired.user.direct_message(http, CreateMessage::new().content("Your global gag has expired"       )).await
+        };
+        if let Err(e) = result {
+            eprintln!("Error: Couldn't announce that {}'s gag in {:?} expired: {e}", expired.user, expired.scope);
+        }
+    }
+}
+
+/// Forever waits until [`State::next_expiry`] then calls [`State::cleanup`] and [`announce`]s the gags that expired.
+///
+/// Wakes early on [`State::gags_changed`] in case a gag that expires sooner was added.
+///
+/// [`State::cleanup`] marks the gaggees as changed, so [`crate::store::persist`] saves them.
+pub async fn expire_gags(state: Arc<State>, http: Arc<Http>) {
+    loop {
+        let wait = state.next_expiry().map(|until| until.signed_duration_since(*Timestamp::now()).to_std().unwrap_or_default());
+        tokio::select! {
+            () = state.gags_changed.notified() => {},
+            () = async {
+                match wait {
+                    Some(wait) => tokio::time::sleep(wait).await,
+                    None       => std::future::pending().await
+                }
+            } => {}
+        }
+        announce(&http, state.cleanup(Timestamp::now())).await;
+    }
+}
@@ -0,0 +1,192 @@
+//! Statistics for tuning gag modes without running the bot.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::types::*;
+
+/// The word length [`entropy`] averages over.
+const ENTROPY_WORD_LENGTH: usize = 5;
+
+/// The widest a bar in [`MuffleStats::histogram`] can be.
+const HISTOGRAM_WIDTH: usize = 40;
+
+/// Statistics about what a gag mode turned some text into.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MuffleStats {
+    /// How many times each letter was output, lowercased.
+    pub letters: BTreeMap<char, usize>,
+    /// The amount of words in the inputs.
+    pub input_words: usize,
+    /// The amount of letters in the inputs' words.
+    pub input_letters: usize,
+    /// The amount of words in the outputs.
+    pub output_words: usize,
+    /// The amount of letters in the outputs' words.
+    pub output_letters: usize
+}
+
+impl MuffleStats {
+    /// Adds an input and what it was rewritten to.
+    pub fn add(&mut self, input: &str, output: &str) {
+        for c in output.chars().filter(|c| c.is_alphabetic()).flat_map(char::to_lowercase) {
+            let count = self.letters.entry(c).or_default();
+            *count = count.saturating_add(1);
+        }
+        let (input_words, input_letters) = word_stats(input);
+        self.input_words   = self.input_words  .saturating_add(input_words);
+        self.input_letters = self.input_letters.saturating_add(input_letters);
+        let (output_words, output_letters) = word_stats(output);
+        self.output_words   = self.output_words  .saturating_add(output_words);
+        self.output_letters = self.output_letters.saturating_add(output_letters);
+    }
+
+    /// The average amount of letters in each input word.
+    ///
+    /// If no words were input, returns [`None`].
+    pub fn average_input_word_length(&self) -> Option<f64> {
+        ratio(self.input_letters, self.input_words)
+    }
+
+    /// The average amount of letters in each output word.
+    ///
+    /// If no words were output, returns [`None`].
+    pub fn average_output_word_length(&self) -> Option<f64> {
+        ratio(self.output_letters, self.output_words)
+    }
+
+    /// A histogram of [`Self::letters`], most common first.
+    pub fn histogram(&self) -> String {
+        let total = self.letters.values().sum::<usize>();
+        let max = self.letters.values().copied().max().unwrap_or_default();
+        let mut letters = self.letters.iter().collect::<Vec<_>>();
+        letters.sort_by(|(c1, n1), (c2, n2)| n2.cmp(n1).then(c1.cmp(c2)));
+        letters.into_iter().map(|(c, count)| format!(
+            "{c} {count:>6} {:>5.1}% {}\n",
+            ratio(*count, total).unwrap_or_default() * 100.0,
+            "#".repeat((ratio(*count, max).unwrap_or_default() * HISTOGRAM_WIDTH as f64).round() as usize)
+        )).collect()
+    }
+}
+
+/// Returns the amount of [`WORD`]s in `text` and the amount of letters in them.
+fn word_stats(text: &str) -> (usize, usize) {
+    WORD.find_iter(text).fold((0, 0), |(words, letters), found| (words.saturating_add(1), letters.saturating_add(found.as_str().chars().filter(|c| c.is_alphabetic()).count())))
+}
+
+/// `x / y`, or [`None`] if `y` is 0.
+fn ratio(x: usize, y: usize) -> Option<f64> {
+    (y != 0).then(|| x as f64 / y as f64)
+}
+
+/// The average entropy of each letter a [`MarkovGagMode`] outputs, in bits, over a word of [`ENTROPY_WORD_LENGTH`] letters.
+///
+/// Higher means more varied muffles. A chain that always outputs the same thing has an entropy of 0.
+///
+/// Doesn't include [`MarkovGagMode::scripts`].
+pub fn entropy(mode: &MarkovGagMode) -> f64 {
+    let next = mode.next.iter().map(|weights| probabilities(weights)).collect::<Vec<_>>();
+    let mut state = probabilities(&mode.first);
+    let mut total = shannon(&state);
+    for _ in 1..ENTROPY_WORD_LENGTH {
+        total += state.iter().zip(&next).map(|(p, next)| p * shannon(next)).sum::<f64>();
+        state = (0..state.len()).map(|to| state.iter().zip(&next).map(|(p, next)| p * next[to]).sum()).collect();
+    }
+    total / ENTROPY_WORD_LENGTH as f64
+}
+
+/// Turns weights into probabilities.
+///
+/// If all weights are 0, so are all probabilities.
+fn probabilities(weights: &[u8]) -> Vec<f64> {
+    let total = weights.iter().map(|weight| usize::from(*weight)).sum::<usize>();
+    weights.iter().map(|weight| ratio((*weight).into(), total).unwrap_or_default()).collect()
+}
+
+/// The Shannon entropy of `probabilities`, in bits.
+fn shannon(probabilities: &[f64]) -> f64 {
+    -probabilities.iter().filter(|p| **p > 0.0).map(|p| p * p.log2()).sum::<f64>()
+}
+
+/// Makes a [`MarkovGagMode`] that sounds like `corpus` by counting which letters start words and which letters follow each other.
+///
+/// Letters are lowercased, and letters that only end words go to the same states words start with.
+///
+/// If `corpus` has no letters, returns [`None`].
+pub fn train(corpus: &str) -> Option<MarkovGagMode> {
+    let letters = corpus.chars().filter(|c| c.is_alphabetic()).flat_map(char::to_lowercase).collect::<BTreeSet<_>>();
+    let index = letters.iter().enumerate().map(|(i, c)| (*c, i)).collect::<HashMap<_, _>>();
+    let mut first = vec![0usize; letters.len()];
+    let mut next = vec![vec![0usize; letters.len()]; letters.len()];
+    for word in WORD.find_iter(corpus) {
+        let mut prev = None::<usize>;
+        for state in word.as_str().chars().filter(|c| c.is_alphabetic()).flat_map(char::to_lowercase).map(|c| index[&c]) {
+            let count = match prev {
+                Some(prev) => &mut next[prev][state],
+                None => &mut first[state]
+            };
+            *count = count.saturating_add(1);
+            prev = Some(state);
+        }
+    }
+    if letters.is_empty() {return None;}
+    Some(MarkovGagMode {
+        chars: letters.into_iter().map(String::from).collect(),
+        next: next.iter().map(|counts| if counts.iter().all(|count| *count == 0) {to_weights(&first)} else {to_weights(counts)}).collect(),
+        first: to_weights(&first),
+        open: None,
+        scripts: HashMap::new()
+    })
+}
+
+/// Scales counts down to weights that fit in a [`u8`] if needed, keeping non-zero counts non-zero.
+fn to_weights(counts: &[usize]) -> Vec<u8> {
+    let max = counts.iter().copied().max().unwrap_or_default();
+    counts.iter().map(|count| match u8::try_from(max) {
+        Ok(_) => *count as u8,
+        Err(_) if *count == 0 => 0,
+        Err(_) => (ratio(*count, max).unwrap_or_default() * f64::from(u8::MAX)).round().max(1.0) as u8
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Makes a [`MarkovGagMode`] from its [`MarkovGagMode::first`] and [`MarkovGagMode::next`].
+    fn chain(first: Vec<u8>, next: Vec<Vec<u8>>) -> MarkovGagMode {
+        serde_json::from_value(serde_json::json!({
+            "chars": (0..first.len()).map(|state| state.to_string()).collect::<Vec<_>>(),
+            "first": first,
+            "next": next
+        })).expect("The test chain to be valid")
+    }
+
+    #[test]
+    fn entropies() {
+        assert_eq!(entropy(&chain(vec![1, 0], vec![vec![0, 1], vec![1, 0]])), 0.0);
+        assert!((entropy(&chain(vec![1, 1], vec![vec![1, 1], vec![1, 1]])) - 1.0).abs() < 1e-9);
+        assert!((entropy(&chain(vec![1, 0], vec![vec![1, 1], vec![0, 1]])) - 0.375).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stats() {
+        let mut stats = MuffleStats::default();
+        stats.add("Hello there", "Hmm mmmff");
+        stats.add("don't", "Mf");
+        assert_eq!(stats.letters, [('f', 3), ('h', 1), ('m', 6)].into());
+        assert_eq!(stats.average_input_word_length(), Some(14.0 / 3.0));
+        assert_eq!(stats.average_output_word_length(), Some(10.0 / 3.0));
+        assert_eq!(stats.histogram().lines().next(), Some(format!("m      6  60.0% {}", "#".repeat(40))).as_deref());
+    }
+
+    #[test]
+    fn training() {
+        let mode = train("Mmph, hmmf! mmmh").expect("The corpus to have letters");
+        assert_eq!(mode.chars, ["f", "h", "m", "p"]);
+        assert_eq!(mode.first, [0, 1, 2, 0]);
+        assert_eq!(mode.next, [[0, 1, 2, 0], [0, 0, 1, 0], [1, 1, 4, 1], [0, 1, 0, 0]]);
+        assert!(GagMode::Markov(mode).problems(&GagModeRegistry::builtin()).is_empty());
+        assert_eq!(train("123 ..."), None);
+        assert_eq!(to_weights(&[1000, 500, 1, 0]), [255, 128, 1, 0]);
+    }
+}
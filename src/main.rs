@@ -2,19 +2,26 @@
 
 use std::fs::read_to_string;
 use std::path::PathBuf;
-use std::fs::OpenOptions;
-use std::io::Write;
-use std::sync::OnceLock;
+use std::io::Read;
+use std::sync::Arc;
 
 use serenity::prelude::*;
 use serenity::client::FullEvent;
 use poise::BoxFuture;
 use serenity::builder::CreateMessage;
 use clap::{Parser, Subcommand};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 
 mod commands;
 mod types;
 mod util;
+mod markup;
+mod lab;
+mod persistence;
+mod migrations;
+mod store;
+mod expiry;
 
 use types::*;
 
@@ -22,45 +29,121 @@ use types::*;
 pub struct Args {
     /// The thing to do/
     #[command(subcommand)]
-    mode: Mode
+    mode: Mode,
+    /// A directory of extra gag mode files to load on top of the built-in ones.
+    ///
+    /// Files with the same name as a built-in gag mode replace it.
+    #[arg(long, global = true)]
+    gag_modes: Option<PathBuf>
 }
 
+/// The things the binary can do.
 #[derive(Subcommand)]
 enum Mode {
     /// Run the bot
     RunBot {
-        /// The path of the state.json file.
+        /// The path of the state.json file, or the database if --store is sqlite.
         #[arg(long, default_value = "state.json")]
-        state: PathBuf
+        state: PathBuf,
+        /// How to store the state.
+        #[arg(long, value_enum, default_value_t = store::StoreKind::Json)]
+        store: store::StoreKind
     },
     /// Test a gag mode.
     TestGagMode {
         /// The gag mode to test.
         #[arg(long)]
-        gag_mode: GagModeName,
+        gag_mode: String,
         /// The text to test it with.
-        text: String,
+        ///
+        /// If neither this nor --file is given, the text is read from STDIN.
+        text: Option<String>,
+        /// Read the text to test it with from a file.
+        #[arg(long, conflicts_with = "text")]
+        file: Option<PathBuf>,
+        /// Another gag mode to rewrite the same text with, to compare them. Can be given multiple times.
+        #[arg(long = "compare")]
+        compare: Vec<String>,
+        /// Print each gag mode's letter histogram, average word length, and entropy.
+        #[arg(long)]
+        stats: bool,
         /// The amount of times to test it.
+        #[arg(long, default_value_t = 1)]
+        count: u8,
+        /// Seed the RNG to get the same output every time.
+        #[arg(long)]
+        seed: Option<u64>,
+        /// The percent of words to muffle.
+        #[arg(long, default_value_t = 100)]
+        intensity: u8,
+        /// Keep each word's length, syllables, and capitalization.
+        #[arg(long)]
+        word_shape: bool,
+        /// A filter to stack on the gag mode. Can be given multiple times and is applied in order.
+        #[arg(long = "filter")]
+        filters: Vec<GagFilter>,
+        /// A word to never muffle. Can be given multiple times.
+        #[arg(long = "allow")]
+        allowed_words: Vec<String>
+    },
+    /// Make a gag mode file from example muffles like "mmph hmmf mmmh" and print it.
+    TrainGagMode {
+        /// The file of example muffles.
+        ///
+        /// If not given, the examples are read from STDIN.
+        corpus: Option<PathBuf>,
+        /// The name to show to users.
         #[arg(long)]
-        count: u8
+        display_name: String,
+        /// The icon. Usually an emoji.
+        #[arg(long, default_value = "❔")]
+        icon: String
+    },
+    /// Check every gag mode for problems like dead ends and unreachable states.
+    ///
+    /// Exits with code 1 if any gag mode has a problem that can make it fail to gag messages.
+    ValidateGagModes
+}
+
+/// Prints every [`GagModeProblem`] in the installed [`GagModeRegistry`] to STDERR.
+///
+/// Returns [`true`] if any of them are [fatal](GagModeProblem::is_fatal).
+fn report_gag_mode_problems() -> bool {
+    let mut fatal = false;
+    for (name, problems) in GagModeRegistry::get().problems() {
+        for problem in problems {
+            eprintln!("{}: Gag mode {name}: {problem}", if problem.is_fatal() {"Error"} else {"Warning"});
+            fatal |= problem.is_fatal();
+        }
     }
+    fatal
 }
 
 /// Gag a message if its user has a gag and no safeword active.
-fn gag_handler<'a>(ctx: &'a Context, event: &'a FullEvent, _: poise::FrameworkContext<'a, State, serenity::Error>, state: &'a State) -> BoxFuture<'a, Result<(), serenity::Error>> {
+fn gag_handler<'a>(ctx: &'a Context, event: &'a FullEvent, _: poise::FrameworkContext<'a, Arc<State>, serenity::Error>, state: &'a Arc<State>) -> BoxFuture<'a, Result<(), serenity::Error>> {
     Box::pin(async move {
-        if let FullEvent::Message{new_message: msg} = event && let Some(action) = state.get_action(msg) {
+        if let FullEvent::Message{new_message: msg} = event
+            && state.has_gags(msg.author.id)
+            && let Some(action) = state.get_action(msg, util::gag_location(ctx, msg.channel_id, msg.guild_id).await) {
             match action {
-                MessageAction::Gag(mode) => {
+                MessageAction::Gag {config, preserve_markup, expired} => {
+                    let content = match util::to_gagged_message(&msg.content, &config, preserve_markup, &msg.author) {
+                        Ok(content) => content,
+                        Err(e) => {
+                            eprintln!("Error: Couldn't gag message {} with gag mode {}: {e}", msg.id, config.mode);
+                            return Ok(());
+                        }
+                    };
                     let mut new_message = CreateMessage::new()
                         .allowed_mentions(Default::default())
-                        .content(util::to_gagged_message(&msg.content, mode, &msg.author));
+                        .content(content);
                     if let Some(ref ref_msg) = msg.referenced_message {new_message = new_message.reference_message(&**ref_msg);}
                     msg.channel_id.send_message(
                         &ctx.http,
                         new_message
                     ).await?;
                     msg.delete(&ctx.http).await?;
+                    expiry::announce(&ctx.http, expired.into_iter().collect()).await;
                 },
                 MessageAction::WarnTooLong(max_length) => {
                     msg.reply(
@@ -78,61 +161,156 @@ fn gag_handler<'a>(ctx: &'a Context, event: &'a FullEvent, _: poise::FrameworkCo
     })
 }
 
-/// Because [`FrameworkOptions::post_command`] takes a function pointer, [`Mode::RunBot::state`] has to be stored somewhere a function pointer can reach.
-static STATE_PATH: OnceLock<PathBuf> = OnceLock::new();
+/// Waits for Ctrl+C or, on unix, SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).expect("Listening for SIGTERM to work");
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.expect("Listening for Ctrl+C to work"),
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.expect("Listening for Ctrl+C to work");
+}
 
 #[tokio::main]
 async fn main() {
-    match Args::parse() {
-        Args {mode: Mode::RunBot {state: state_path}} => {
-            let state: State = serde_json::from_str(&read_to_string(&state_path).expect("The state file to exist")).expect("The state to be valid");
+    let args = Args::parse();
+
+    let mut gag_mode_registry = GagModeRegistry::builtin();
+    if let Some(dir) = &args.gag_modes {
+        gag_mode_registry.load_dir(dir).expect("The gag modes directory to be valid");
+    }
+    gag_mode_registry.install().expect("The GagModeRegistry to not have already been installed");
+
+    match args {
+        Args {mode: Mode::RunBot {state: state_path, store: store_kind}, ..} => {
+            if report_gag_mode_problems() {
+                eprintln!("Error: Not starting because a gag mode could fail to gag messages.");
+                std::process::exit(1);
+            }
 
-            STATE_PATH.set(state_path).expect("The STATE_PATH static to not have already been set");
+            let store = store_kind.open(&state_path).expect("The state store to be openable");
+            let state = Arc::new(store.load().expect("The state to be valid"));
+
+            tokio::spawn(store::persist(state.clone(), store.clone()));
 
             let framework = poise::Framework::builder()
                 .options(poise::FrameworkOptions {
                     commands: vec![
                         commands::gag(), commands::ungag(), commands::gagged(), commands::change_gag(),
-                        commands::tie(), commands::untie(),
+                        commands::tie(), commands::untie(), commands::key(),
                         commands::struggle(), commands::let_me_out(),
                         commands::trust(),
                         commands::safeword(), commands::unsafeword(),
                         commands::export(), commands::import(), commands::wipe_my_data(),
                         commands::status(),
-                        commands::set_max_message_length_to_gag(),
+                        commands::set_max_message_length_to_gag(), commands::set_preserve_markup(), commands::set_announce_gag_expiry(), commands::set_thread_inherits_gags(),
                         commands::gag_default()
                     ],
                     event_handler: gag_handler,
-                    pre_command: move |ctx: poise::Context<'_, State, _>| Box::pin(async move {ctx.data().cleanup(ctx.created_at());}),
-                    post_command: move |ctx: poise::Context<'_, State, _>| Box::pin(async move {
-                        ctx.data().cleanup(ctx.created_at());
-
-                        OpenOptions::new().write(true).truncate(true)
-                            .open(STATE_PATH.get().expect("The STATE_PATH to have been set by now"))
-                            .expect("The file to be openable")
-                            .write_all(serde_json::to_string_pretty(ctx.data()).expect("The state to be serializable").as_bytes())
-                            .expect("The file to be writable")
-                    }),
                     allowed_mentions: Some(Default::default()),
                     ..Default::default()
                 })
-                .setup(|ctx, _, framework| {
-                    Box::pin(async move {
-                        poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                        Ok(state)
-                    })
+                .setup({
+                    let state = state.clone();
+                    |ctx, _, framework| {
+                        Box::pin(async move {
+                            poise::builtins::register_globally(ctx, &framework.options().commands).await?;
+                            Ok(state)
+                        })
+                    }
                 })
                 .build();
 
             let token = std::env::var("GOOFUS_GAGGER_KEY").expect("An API key in the environment variable GOOFUS_GAGGER_KEY");
-            let intents = GatewayIntents::MESSAGE_CONTENT | GatewayIntents::GUILD_MESSAGES;
+            // GUILDS keeps channels cached so category gags can find a channel's category.
+            let intents = GatewayIntents::MESSAGE_CONTENT | GatewayIntents::GUILD_MESSAGES | GatewayIntents::GUILDS;
 
             let mut client = Client::builder(token, intents).framework(framework).await.expect("Error creating client.");
 
+            tokio::spawn(expiry::expire_gags(state.clone(), client.http.clone()));
+
+            let shard_manager = client.shard_manager.clone();
+            tokio::spawn(async move {
+                shutdown_signal().await;
+                eprintln!("Shutting down.");
+                shard_manager.shutdown_all().await;
+            });
+
             client.start().await.expect("Bot to work");
+
+            store::save_changes(&state, &*store);
+        },
+        Args {mode: Mode::TestGagMode {gag_mode, text, file, compare, stats, count, seed, intensity, word_shape, filters, allowed_words}, ..} => {
+            // Files and STDIN usually end with a newline that isn't part of the text.
+            let text = match (text, file) {
+                (Some(text), _   ) => text,
+                (None, Some(file)) => read_to_string(file).expect("The file to be readable").trim_end_matches('\n').to_string(),
+                (None, None      ) => {
+                    let mut text = String::new();
+                    std::io::stdin().read_to_string(&mut text).expect("STDIN to be readable");
+                    text.trim_end_matches('\n').to_string()
+                }
+            };
+            let names = std::iter::once(gag_mode).chain(compare).map(|name| name.parse::<GagModeName>().expect("The gag mode to exist")).collect::<Vec<_>>();
+            let seed = seed.unwrap_or_else(rand::random);
+            let allowed_words = allowed_words.iter().map(|word| word.to_lowercase()).collect();
+            let options = RewriteOptions {intensity: Intensity::new(intensity), word_shape, filters: &filters, allowed_words: &allowed_words};
+            let width = names.iter().map(|name| name.0.len()).max().unwrap_or_default();
+
+            let mut rngs = names.iter().map(|_| ChaCha8Rng::seed_from_u64(seed)).collect::<Vec<_>>();
+            let mut muffle_stats = names.iter().map(|_| lab::MuffleStats::default()).collect::<Vec<_>>();
+            for _ in 0..count {
+                for ((name, rng), muffle_stats) in names.iter().zip(&mut rngs).zip(&mut muffle_stats) {
+                    let output = name.get().expect("The gag mode to exist").rewrite_with_rng(&text, options, rng).expect("The gag mode to be valid");
+                    muffle_stats.add(&text, &output);
+                    if names.len() == 1 {
+                        println!("{output}");
+                    } else {
+                        println!("{:width$}: {output}", name.0);
+                    }
+                }
+            }
+
+            if stats {
+                for (name, muffle_stats) in names.iter().zip(&muffle_stats) {
+                    println!("\n{name}:");
+                    if let (Some(input), Some(output)) = (muffle_stats.average_input_word_length(), muffle_stats.average_output_word_length()) {
+                        println!("Average word length: {input:.2} letters in, {output:.2} letters out");
+                    }
+                    if let Some(GagMode::Markov(mode)) = name.get() {
+                        println!("Entropy: {:.3} bits per letter", lab::entropy(mode));
+                        let mut scripts = mode.scripts.iter().collect::<Vec<_>>();
+                        scripts.sort_by_key(|(script, _)| **script);
+                        for (script, chain) in scripts {
+                            println!("{script:?} entropy: {:.3} bits per letter", lab::entropy(chain));
+                        }
+                    }
+                    print!("{}", muffle_stats.histogram());
+                }
+            }
+        },
+        Args {mode: Mode::TrainGagMode {corpus, display_name, icon}, ..} => {
+            let corpus = match corpus {
+                Some(corpus) => read_to_string(corpus).expect("The corpus to be readable"),
+                None => {
+                    let mut corpus = String::new();
+                    std::io::stdin().read_to_string(&mut corpus).expect("STDIN to be readable");
+                    corpus
+                }
+            };
+            let definition = GagModeDefinition {
+                display_name,
+                icon,
+                mode: GagMode::Markov(lab::train(&corpus).expect("The corpus to have letters"))
+            };
+            println!("{}", serde_json::to_string_pretty(&definition).expect("The gag mode to be serializable"));
         },
-        Args {mode: Mode::TestGagMode {gag_mode, text, count}} => for _ in 0..count {
-            println!("{:?}", gag_mode.get().rewrite(&text));
+        Args {mode: Mode::ValidateGagModes, ..} => if report_gag_mode_problems() {
+            std::process::exit(1);
         }
     }
 }
@@ -0,0 +1,185 @@
+//! Splitting messages into the parts to gag and the parts to keep as is.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::types::WORD;
+
+/// A part of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment<'a> {
+    /// Markdown at the start of a line, like block quotes, headings, and list markers.
+    LinePrefix(&'a str),
+    /// Markdown or Discord markup that should be kept as is.
+    Keep(&'a str),
+    /// Prose that should be gagged.
+    Gag(&'a str)
+}
+
+impl<'a> Segment<'a> {
+    /// The text of the segment.
+    pub fn as_str(&self) -> &'a str {
+        match self {
+            Self::LinePrefix(x) | Self::Keep(x) | Self::Gag(x) => x
+        }
+    }
+}
+
+/// The markdown that can go at the start of a line, in any combination.
+///
+/// Block quotes, headings, subtext, and list markers.
+const LINE_PREFIX: &str = r"(?m:^(?:>>> |> )?(?:[ \t]*(?:-\# |\#{1,3} |[-*] |\d+\. ))*)";
+
+/// The markdown that can go anywhere in a line.
+const INLINE_MARKDOWN: &str = r"(?x:
+      \\[^\w\s]      # Escaped characters.
+    | \*{1,3}        # Italics and bold.
+    | _{1,3}         # Italics and underline.
+    | ~~             # Strikethrough.
+    | \|\|           # Spoilers.
+)";
+
+/// Discord markup that [`segments`] only keeps if told to.
+const DISCORD_MARKUP: &str = r"(?x:
+      <@[!&]?\d+>                       # User and role mentions.
+    | <\#\d+>                           # Channel mentions.
+    | </[\w\ -]+:\d+>                   # Slash command mentions.
+    | <a?:\w+:\d+>                      # Custom emoji.
+    | <t:-?\d+(?::[tTdDfFR])?>          # Timestamps.
+    | @(?:everyone|here)\b              # Pings.
+    | <https?://[^\s>]+>                # Links with embeds suppressed.
+    | https?://[^\s<]+[^\s<.,:;!?'\)]   # Links.
+    | ```[\s\S]*?```                    # Code blocks.
+    | ``[^`][\s\S]*?``                  # Inline code containing backticks.
+    | `[^`]+`                           # Inline code.
+)";
+
+/// The [`Regex`] [`segments`] uses when not keeping [`DISCORD_MARKUP`].
+static MARKDOWN: LazyLock<Regex> = LazyLock::new(|| Regex::new(&format!("(?P<prefix>{LINE_PREFIX})|{INLINE_MARKDOWN}")).expect("The MARKDOWN regex to be valid"));
+/// The [`Regex`] [`segments`] uses when keeping [`DISCORD_MARKUP`].
+///
+/// [`DISCORD_MARKUP`] goes first so code blocks and links don't get split up by markdown inside them.
+static MARKUP: LazyLock<Regex> = LazyLock::new(|| Regex::new(&format!("{DISCORD_MARKUP}|(?P<prefix>{LINE_PREFIX})|{INLINE_MARKDOWN}")).expect("The MARKUP regex to be valid"));
+
+/// Splits `text` into [`Segment`]s so that only the prose gets gagged.
+///
+/// Markdown formatting is always kept. If `preserve_markup` is [`true`], mentions, custom emoji, timestamps, links, and code are also kept.
+///
+/// Concatenating the segments always gives back `text`.
+pub fn segments(text: &str, preserve_markup: bool) -> Vec<Segment<'_>> {
+    let regex = if preserve_markup {&*MARKUP} else {&*MARKDOWN};
+    let mut ret = Vec::new();
+    let mut last = 0;
+    for captures in regex.captures_iter(text) {
+        let found = captures.get(0).expect("The whole match to always exist");
+        if found.is_empty() {continue;}
+        if found.start() > last {ret.push(Segment::Gag(&text[last..found.start()]));}
+        ret.push(match captures.name("prefix") {
+            Some(_) => Segment::LinePrefix(found.as_str()),
+            None    => Segment::Keep(found.as_str())
+        });
+        last = found.end();
+    }
+    if last < text.len() {ret.push(Segment::Gag(&text[last..]));}
+    ret
+}
+
+/// Makes every line of `text` subtext.
+///
+/// Block quote markers are kept in front, headings become subtext, and lines that are already subtext, blank, or in code blocks are left alone.
+pub fn subtext(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    let mut in_code_block = false;
+    for line in text.split_inclusive('\n') {
+        let was_in_code_block = in_code_block;
+        if line.matches("```").count() % 2 == 1 {in_code_block = !in_code_block;}
+        let (quote, rest) = [">>> ", "> "].into_iter().find_map(|quote| line.strip_prefix(quote).map(|rest| (quote, rest))).unwrap_or(("", line));
+        if was_in_code_block || rest.trim().is_empty() || rest.starts_with("-# ") || rest.starts_with("```") {
+            ret.push_str(line);
+        } else {
+            ret.push_str(quote);
+            ret.push_str("-# ");
+            ret.push_str(rest.strip_prefix("### ").or_else(|| rest.strip_prefix("## ")).or_else(|| rest.strip_prefix("# ")).unwrap_or(rest));
+        }
+    }
+    ret
+}
+
+/// The number of [`WORD`]s in the [`Segment::Gag`]s of `text`, which are the only words a gag muffles.
+pub fn gagged_word_count(text: &str, preserve_markup: bool) -> usize {
+    segments(text, preserve_markup).into_iter().map(|segment| match segment {
+        Segment::Gag(gag) => WORD.find_iter(gag).count(),
+        _ => 0
+    }).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Replaces every letter in the [`Segment::Gag`]s with `m`.
+    fn muffle(text: &str, preserve_markup: bool) -> String {
+        segments(text, preserve_markup).into_iter().map(|segment| match segment {
+            Segment::Gag(gag) => gag.chars().map(|c| if c.is_alphabetic() {'m'} else {c}).collect(),
+            _ => segment.as_str().to_string()
+        }).collect()
+    }
+
+    #[test]
+    fn segments_concatenate_to_text() {
+        for text in [
+            "",
+            "plain",
+            "# Heading\n> - quoted **bold** item\n\n1. one\n2. two",
+            "`code` <@123> https://example.com/a_b?c=d, ok ||spoiler||",
+            "```\nmulti\nline\n```\nafter"
+        ] {
+            for preserve_markup in [false, true] {
+                assert_eq!(segments(text, preserve_markup).into_iter().map(|segment| segment.as_str()).collect::<String>(), text);
+            }
+        }
+    }
+
+    #[test]
+    fn nested_inline_formatting() {
+        assert_eq!(muffle("***bold italic*** and ||**spoiler bold**||", true), "***mmmm mmmmmm*** mmm ||**mmmmmmm mmmm**||");
+        assert_eq!(muffle("__*underlined italics*__ ~~**struck bold**~~", true), "__*mmmmmmmmmm mmmmmmm*__ ~~**mmmmmm mmmm**~~");
+        assert_eq!(muffle("||~~__***everything***__~~||", true), "||~~__***mmmmmmmmmm***__~~||");
+    }
+
+    #[test]
+    fn line_prefixes() {
+        assert_eq!(muffle("# Title\n## Sub\n### Subsub\n-# Small", true), "# mmmmm\n## mmm\n### mmmmmm\n-# mmmmm");
+        assert_eq!(muffle("> - quoted item\n> 2. second\n>>> rest", true), "> - mmmmmm mmmm\n> 2. mmmmmm\n>>> mmmm");
+        assert_eq!(muffle("- item\n  * nested *italic*\n- # heading item", true), "- mmmm\n  * mmmmmm *mmmmmm*\n- # mmmmmmm mmmm");
+        assert_eq!(segments("> # hi", true)[0], Segment::LinePrefix("> # "));
+        assert_eq!(segments("hi # there", true)[0], Segment::Gag("hi # there"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(muffle(r"\*not italic\* \|\|nor spoiler\|\|", true), r"\*mmm mmmmmm\* \|\|mmm mmmmmmm\|\|");
+    }
+
+    #[test]
+    fn discord_markup() {
+        let text = "**hey** <@123>, see <#456> https://example.com/path at <t:1700000000:R> <:blob:789> `some code`";
+        assert_eq!(muffle(text, true ), "**mmm** <@123>, mmm <#456> https://example.com/path mm <t:1700000000:R> <:blob:789> `some code`");
+        assert_eq!(muffle(text, false), "**mmm** <@123>, mmm <#456> mmmmm://mmmmmmm.mmm/mmmm mm <m:1700000000:m> <:mmmm:789> `mmmm mmmm`");
+        assert_eq!(muffle("```rust\n# not a heading\n**nor bold**\n```", true), "```rust\n# not a heading\n**nor bold**\n```");
+    }
+
+    #[test]
+    fn subtexts() {
+        assert_eq!(subtext("hi\n\n> quoted\n## heading\n-# already"), "-# hi\n\n> -# quoted\n-# heading\n-# already");
+        assert_eq!(subtext("code:\n```\n# not a heading\n```\nafter"), "-# code:\n```\n# not a heading\n```\n-# after");
+    }
+
+    #[test]
+    fn gagged_words() {
+        let text = "see ```\nsome code here\n``` and https://example.com/lots/of/words <@123>";
+        assert_eq!(gagged_word_count(text, true ), 2);
+        assert_eq!(gagged_word_count(text, false), WORD.find_iter(text).count());
+    }
+}
@@ -0,0 +1,189 @@
+//! Upgrading state files and [`PortableGaggee`](crate::types::PortableGaggee) exports made by older versions of the bot.
+//!
+//! Both have a `version` field. Files without one are version 0.
+
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use thiserror::Error;
+
+/// The version of state files and [`PortableGaggee`](crate::types::PortableGaggee) exports this version of the bot makes.
+pub const CURRENT_VERSION: u64 = 2;
+
+/// A function that upgrades a file by one version.
+type Migration = fn(&mut Value) -> Result<(), MigrationError>;
+
+/// The [`Migration`]s for state files, where the `n`th upgrades version `n` to `n + 1`.
+const STATE_MIGRATIONS: &[Migration] = &[
+    add_version,
+    scope_state_gags
+];
+
+/// The [`Migration`]s for [`PortableGaggee`](crate::types::PortableGaggee) exports, where the `n`th upgrades version `n` to `n + 1`.
+const PORTABLE_GAGGEE_MIGRATIONS: &[Migration] = &[
+    add_version,
+    scope_portable_gaggee_gags
+];
+
+/// The enum of errors [`migrate`] can return.
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    /// Returned when the file isn't a JSON object.
+    #[error("The file isn't a JSON object.")]
+    NotAnObject,
+    /// Returned when the `version` field isn't a non-negative integer.
+    #[error("The version field isn't a non-negative integer.")]
+    InvalidVersion,
+    /// Returned when the file was made by a newer version of the bot.
+    #[error("The file is version {0}, but this version of the bot only understands up to version {CURRENT_VERSION}.")]
+    TooNew(u64),
+    /// Returned when the upgraded file is invalid.
+    #[error(transparent)]
+    Invalid(#[from] serde_json::Error)
+}
+
+/// The kinds of files that can be migrated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// A state file.
+    State,
+    /// A [`PortableGaggee`](crate::types::PortableGaggee) export.
+    PortableGaggee
+}
+
+impl FileKind {
+    /// The [`Migration`]s for the kind of file.
+    fn migrations(self) -> &'static [Migration] {
+        match self {
+            Self::State          => STATE_MIGRATIONS,
+            Self::PortableGaggee => PORTABLE_GAGGEE_MIGRATIONS
+        }
+    }
+}
+
+/// Upgrades `value` to [`CURRENT_VERSION`] and deserializes it.
+/// # Errors
+/// If `value` isn't a JSON object, returns the error [`MigrationError::NotAnObject`].
+///
+/// If the `version` field isn't a non-negative integer, returns the error [`MigrationError::InvalidVersion`].
+///
+/// If the `version` field is newer than [`CURRENT_VERSION`], returns the error [`MigrationError::TooNew`].
+///
+/// If a migration or deserializing the upgraded file fails, that error is returned.
+pub fn migrate<T: DeserializeOwned>(kind: FileKind, mut value: Value) -> Result<T, MigrationError> {
+    let version = match value.as_object().ok_or(MigrationError::NotAnObject)?.get("version") {
+        Some(version) => version.as_u64().ok_or(MigrationError::InvalidVersion)?,
+        None => 0
+    };
+    let migrations = usize::try_from(version).ok().and_then(|version| kind.migrations().get(version..)).ok_or(MigrationError::TooNew(version))?;
+    for migration in migrations {
+        migration(&mut value)?;
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Parses `json` and passes it to [`migrate`].
+/// # Errors
+/// If `json` isn't valid JSON, returns the error [`MigrationError::Invalid`].
+///
+/// If the call to [`migrate`] returns an error, that error is returned.
+pub fn migrate_str<T: DeserializeOwned>(kind: FileKind, json: &str) -> Result<T, MigrationError> {
+    migrate(kind, serde_json::from_str(json)?)
+}
+
+/// Serializes `value` with its `version` set to [`CURRENT_VERSION`].
+/// # Panics
+/// If `value` can't be serialized into a JSON object, panics.
+pub fn versioned<T: Serialize>(value: &T) -> Value {
+    let mut ret = serde_json::to_value(value).expect("Serialization to never fail");
+    ret.as_object_mut().expect("The value to serialize to a JSON object").insert("version".to_string(), CURRENT_VERSION.into());
+    ret
+}
+
+/// Upgrades a version 0 file to version 1, which only added the `version` field.
+fn add_version(value: &mut Value) -> Result<(), MigrationError> {
+    value.as_object_mut().ok_or(MigrationError::NotAnObject)?.insert("version".to_string(), 1.into());
+    Ok(())
+}
+
+/// Turns a map of gags keyed by channel ID into one keyed by [`GagScope`](crate::types::GagScope), like `channel:{id}`.
+///
+/// Leaves anything that isn't a JSON object as is for deserializing to reject.
+fn scope_gags(gags: &mut Value) {
+    if let Some(gags) = gags.as_object_mut() {
+        *gags = std::mem::take(gags).into_iter().map(|(channel, gag)| (format!("channel:{channel}"), gag)).collect();
+    }
+}
+
+/// Upgrades a version 1 state file to version 2, where gags are keyed by [`GagScope`](crate::types::GagScope) instead of channel.
+fn scope_state_gags(value: &mut Value) -> Result<(), MigrationError> {
+    let value = value.as_object_mut().ok_or(MigrationError::NotAnObject)?;
+    if let Some(gags) = value.get_mut("gags").and_then(Value::as_object_mut) {
+        gags.values_mut().for_each(scope_gags);
+    }
+    value.insert("version".to_string(), 2.into());
+    Ok(())
+}
+
+/// Upgrades a version 1 [`PortableGaggee`](crate::types::PortableGaggee) export to version 2, where gags are keyed by [`GagScope`](crate::types::GagScope) instead of channel.
+fn scope_portable_gaggee_gags(value: &mut Value) -> Result<(), MigrationError> {
+    let value = value.as_object_mut().ok_or(MigrationError::NotAnObject)?;
+    if let Some(gags) = value.get_mut("gags") {scope_gags(gags);}
+    value.insert("version".to_string(), 2.into());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::types::*;
+
+    /// The state file fixture for each version, which all have the same data.
+    const STATE_FIXTURES: [&str; 3] = [
+        include_str!("../fixtures/state/v0.json"),
+        include_str!("../fixtures/state/v1.json"),
+        include_str!("../fixtures/state/v2.json")
+    ];
+
+    /// The [`PortableGaggee`] fixture for each version, which all have the same data.
+    const PORTABLE_GAGGEE_FIXTURES: [&str; 3] = [
+        include_str!("../fixtures/portable_gaggee/v0.json"),
+        include_str!("../fixtures/portable_gaggee/v1.json"),
+        include_str!("../fixtures/portable_gaggee/v2.json")
+    ];
+
+    #[test]
+    fn migrations_cover_every_version() {
+        assert_eq!(STATE_MIGRATIONS.len() as u64, CURRENT_VERSION);
+        assert_eq!(PORTABLE_GAGGEE_MIGRATIONS.len() as u64, CURRENT_VERSION);
+        assert_eq!(STATE_FIXTURES.len() as u64, CURRENT_VERSION + 1);
+        assert_eq!(PORTABLE_GAGGEE_FIXTURES.len() as u64, CURRENT_VERSION + 1);
+    }
+
+    #[test]
+    fn state_fixtures() {
+        let current = migrate_str::<State>(FileKind::State, STATE_FIXTURES[CURRENT_VERSION as usize]).expect("The current fixture to be valid");
+        assert_eq!(current.user(100.into()).max_msg_length, Some(500));
+        for fixture in STATE_FIXTURES {
+            let state = migrate_str::<State>(FileKind::State, fixture).expect("The fixture to be valid");
+            for user in [100, 101] {
+                assert_eq!(state.export(user.into()), current.export(user.into()));
+            }
+        }
+    }
+
+    #[test]
+    fn portable_gaggee_fixtures() {
+        let current = migrate_str::<PortableGaggee>(FileKind::PortableGaggee, PORTABLE_GAGGEE_FIXTURES[CURRENT_VERSION as usize]).expect("The current fixture to be valid");
+        assert_eq!(current.max_msg_length, Some(500));
+        assert!(current.gags.as_ref().is_some_and(|gags| gags.contains_key(&GagScope::Channel(300.into()))));
+        for fixture in PORTABLE_GAGGEE_FIXTURES {
+            assert_eq!(migrate_str::<PortableGaggee>(FileKind::PortableGaggee, fixture).expect("The fixture to be valid"), current);
+        }
+    }
+
+    #[test]
+    fn too_new() {
+        assert!(matches!(migrate_str::<PortableGaggee>(FileKind::PortableGaggee, r#"{"version": 999}"#), Err(MigrationError::TooNew(999))));
+    }
+}
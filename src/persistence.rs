@@ -0,0 +1,159 @@
+//! Saving and loading the [`State`] without losing it to crashes.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::types::*;
+use crate::migrations::{self, FileKind, MigrationError};
+
+/// The amount of previous state files [`save`] keeps.
+pub const BACKUPS: usize = 3;
+
+/// The path of the `n`th newest backup of the state file at `path`, like `state.json.1`.
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+    let mut ret = path.as_os_str().to_owned();
+    ret.push(format!(".{n}"));
+    ret.into()
+}
+
+/// The path of the file with the extension `extension` added next to the state file at `path`, like `state.json.tmp`.
+fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+    let mut ret = path.as_os_str().to_owned();
+    ret.push(".");
+    ret.push(extension);
+    ret.into()
+}
+
+/// Saves `state` to `path` such that a crash or full disk at any point leaves either the old or the new state file.
+///
+/// The new state is written to a temporary file that's synced to disk before being renamed over `path`. The previous [`BACKUPS`] state files are kept as `path.1`, `path.2`, etc.
+/// # Errors
+/// If an [`std::io::Error`] is encountered, it's returned and the state file at `path` is left as it was.
+/// # Panics
+/// If `state` can't be serialized, panics.
+pub fn save(state: &State, path: &Path) -> std::io::Result<()> {
+    let temp = sibling_path(path, "tmp");
+    let mut file = File::create(&temp)?;
+    file.write_all(serde_json::to_string_pretty(&migrations::versioned(state)).expect("The state to be serializable").as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+
+    if path.exists() {
+        for n in (1..BACKUPS).rev() {
+            if backup_path(path, n).exists() {fs::rename(backup_path(path, n), backup_path(path, n.saturating_add(1)))?;}
+        }
+        let _ = fs::remove_file(backup_path(path, 1));
+        if fs::hard_link(path, backup_path(path, 1)).is_err() {fs::copy(path, backup_path(path, 1))?;}
+    }
+
+    fs::rename(&temp, path)?;
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        File::open(if dir.as_os_str().is_empty() {Path::new(".")} else {dir})?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// The enum of errors [`load`] can return.
+#[derive(Debug, Error)]
+pub enum LoadStateError {
+    /// Returned when neither the state file nor any of its backups could be loaded.
+    #[error("Neither {0:?} nor any of its backups could be loaded.")]
+    NoValidStateFile(PathBuf)
+}
+
+/// The enum of errors loading a single state file can return.
+#[derive(Debug, Error)]
+enum StateFileError {
+    /// Returned when an [`std::io::Error`] is encountered.
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    /// Returned when the file isn't a valid [`State`] of any version.
+    #[error(transparent)]
+    InvalidState(#[from] MigrationError)
+}
+
+/// Loads a single state file.
+fn load_file(path: &Path) -> Result<State, StateFileError> {
+    Ok(migrations::migrate_str(FileKind::State, &fs::read_to_string(path)?)?)
+}
+
+/// Loads the [`State`] saved by [`save`], upgrading it from older versions if needed.
+///
+/// If the state file at `path` is missing or invalid, the newest valid backup is loaded instead and the invalid file is moved to `path.corrupt` so it doesn't replace any backups.
+///
+/// Every file that fails to load is logged to STDERR.
+/// # Errors
+/// If neither the state file nor any of its backups could be loaded, returns the error [`LoadStateError::NoValidStateFile`].
+pub fn load(path: &Path) -> Result<State, LoadStateError> {
+    for candidate in std::iter::once(path.to_path_buf()).chain((1..=BACKUPS).map(|n| backup_path(path, n))) {
+        if candidate != path && !candidate.exists() {continue;}
+        match load_file(&candidate) {
+            Ok(state) => {
+                if candidate != path {
+                    eprintln!("Error: The state file {path:?} couldn't be loaded, so the backup {candidate:?} was loaded instead. Changes made since that backup are lost.");
+                    if path.exists() && let Err(e) = fs::rename(path, sibling_path(path, "corrupt")) {
+                        eprintln!("Error: Couldn't move the state file {path:?} out of the way: {e}");
+                    }
+                }
+                return Ok(state);
+            },
+            Err(e) => eprintln!("Error: Couldn't load the state file {candidate:?}: {e}")
+        }
+    }
+    Err(LoadStateError::NoValidStateFile(path.to_path_buf()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Makes an empty directory for a test to put state files in.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("goofus-gagger-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("The test directory to be creatable");
+        dir
+    }
+
+    /// Makes a [`State`] with one max message length set, to tell states apart.
+    fn state(max_msg_length: usize) -> State {
+        serde_json::from_value(serde_json::json!({"max_msg_lengths": {"1": max_msg_length}})).expect("The test state to be valid")
+    }
+
+    /// Gets the max message length set by [`state`].
+    fn max_msg_length(state: &State) -> Option<usize> {
+        state.user(1.into()).max_msg_length
+    }
+
+    #[test]
+    fn backups_rotate() {
+        let path = test_dir("backups_rotate").join("state.json");
+        for n in 1..=5 {
+            save(&state(n), &path).expect("Saving to work");
+        }
+        assert_eq!(max_msg_length(&load(&path).expect("Loading to work")), Some(5));
+        for n in 1..=BACKUPS {
+            assert_eq!(max_msg_length(&load_file(&backup_path(&path, n)).expect("The backup to exist")), Some(5 - n));
+        }
+        assert!(!backup_path(&path, BACKUPS + 1).exists());
+        assert!(!sibling_path(&path, "tmp").exists());
+    }
+
+    #[test]
+    fn recovery() {
+        let path = test_dir("recovery").join("state.json");
+        save(&state(1), &path).expect("Saving to work");
+        save(&state(2), &path).expect("Saving to work");
+        fs::write(&path, "{\"max_msg_lengths\": {").expect("Writing to work");
+        assert_eq!(max_msg_length(&load(&path).expect("Loading to work")), Some(1));
+        assert!(sibling_path(&path, "corrupt").exists());
+        assert!(!path.exists());
+
+        fs::remove_file(backup_path(&path, 1)).expect("The backup to exist");
+        assert!(matches!(load(&path), Err(LoadStateError::NoValidStateFile(_))));
+    }
+}
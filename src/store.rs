@@ -0,0 +1,114 @@
+//! Where the [`State`] is kept between runs.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serenity::model::id::UserId;
+use thiserror::Error;
+
+use crate::types::*;
+use crate::persistence::LoadStateError;
+use crate::migrations::MigrationError;
+
+mod json;
+pub use json::*;
+mod sqlite;
+pub use sqlite::*;
+
+/// The enum of errors a [`StateStore`] can return.
+#[derive(Debug, Error)]
+pub enum StoreError {
+    /// Returned when an [`std::io::Error`] is encountered.
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    /// Returned when a [`LoadStateError`] is encountered.
+    #[error(transparent)]
+    LoadStateError(#[from] LoadStateError),
+    /// Returned when a [`rusqlite::Error`] is encountered.
+    #[error(transparent)]
+    SqliteError(#[from] rusqlite::Error),
+    /// Returned when a [`serde_json::Error`] is encountered.
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    /// Returned when a [`MigrationError`] is encountered.
+    #[error(transparent)]
+    MigrationError(#[from] MigrationError)
+}
+
+/// A place to keep the [`State`] between runs.
+pub trait StateStore: Send + Sync {
+    /// Loads the [`State`], upgrading it from older versions if needed.
+    /// # Errors
+    /// If the state can't be loaded, returns an error.
+    fn load(&self) -> Result<State, StoreError>;
+
+    /// Saves the data of the users in `changed`.
+    ///
+    /// Stores that can't save users individually save everything.
+    /// # Errors
+    /// If the state can't be saved, returns an error.
+    fn save(&self, state: &State, changed: &HashSet<UserId>) -> Result<(), StoreError>;
+}
+
+/// How long [`persist`] waits after the last change before saving, so bursts of changes are saved together.
+pub const SAVE_DELAY: Duration = Duration::from_secs(2);
+/// The longest [`persist`] waits after the first change before saving, so constant changes still get saved.
+pub const MAX_SAVE_DELAY: Duration = Duration::from_secs(30);
+
+/// Saves the users in [`State::changed_users`] to `store`.
+///
+/// If saving fails, the error is logged and the users are put back to be saved next time.
+pub fn save_changes(state: &State, store: &dyn StateStore) {
+    let changed = state.take_changed_users();
+    if let Err(e) = store.save(state, &changed) {
+        eprintln!("Error: Couldn't save the state: {e}");
+        state.changed_users.lock().expect("No panics").extend(changed);
+    }
+}
+
+/// Forever waits for [`State::changed`] then, once there have been no changes for [`SAVE_DELAY`], calls [`save_changes`].
+///
+/// Each change restarts the delay, up to [`MAX_SAVE_DELAY`] after the first one.
+///
+/// Anything changed shortly before the bot stops has to be saved by calling [`save_changes`] after it stops.
+pub async fn persist(state: Arc<State>, store: Arc<dyn StateStore>) {
+    loop {
+        state.changed.notified().await;
+        let max_delay = tokio::time::sleep(MAX_SAVE_DELAY);
+        tokio::pin!(max_delay);
+        loop {
+            tokio::select! {
+                () = state.changed.notified()       => {},
+                () = tokio::time::sleep(SAVE_DELAY) => break,
+                () = &mut max_delay                 => break
+            }
+        }
+        let (state, store) = (state.clone(), store.clone());
+        if let Err(e) = tokio::task::spawn_blocking(move || save_changes(&state, &*store)).await {
+            eprintln!("Error: The save task panicked: {e}");
+        }
+    }
+}
+
+/// The kinds of [`StateStore`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StoreKind {
+    /// [`JsonStore`].
+    Json,
+    /// [`SqliteStore`].
+    Sqlite
+}
+
+impl StoreKind {
+    /// Opens a [`StateStore`] of this kind at `path`.
+    /// # Errors
+    /// If the call to [`SqliteStore::open`] returns an error, that error is returned.
+    pub fn open(self, path: &Path) -> Result<Arc<dyn StateStore>, StoreError> {
+        Ok(match self {
+            Self::Json   => Arc::new(JsonStore::new(path.to_path_buf())),
+            Self::Sqlite => Arc::new(SqliteStore::open(path)?)
+        })
+    }
+}
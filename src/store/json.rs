@@ -0,0 +1,43 @@
+//! Keeping the [`State`] in a JSON file.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serenity::model::id::UserId;
+
+use crate::types::*;
+use crate::store::{StateStore, StoreError};
+use crate::persistence;
+
+/// A [`StateStore`] that keeps the whole [`State`] in a JSON file, using [`persistence`] to not lose it to crashes.
+///
+/// Every save rewrites the whole file.
+#[derive(Debug)]
+pub struct JsonStore {
+    /// The path of the state file.
+    pub path: PathBuf,
+    /// Held while saving so two saves don't write the same temporary file at once.
+    saving: Mutex<()>
+}
+
+impl JsonStore {
+    /// Makes a [`Self`] for the state file at `path`.
+    pub fn new(path: PathBuf) -> Self {
+        Self {path, saving: Mutex::new(())}
+    }
+}
+
+impl StateStore for JsonStore {
+    fn load(&self) -> Result<State, StoreError> {
+        Ok(persistence::load(&self.path)?)
+    }
+
+    fn save(&self, state: &State, changed: &HashSet<UserId>) -> Result<(), StoreError> {
+        if !changed.is_empty() {
+            let _saving = self.saving.lock().expect("No panics");
+            persistence::save(state, &self.path)?;
+        }
+        Ok(())
+    }
+}
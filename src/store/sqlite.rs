@@ -0,0 +1,116 @@
+//! Keeping the [`State`] in an SQLite database.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Mutex;
+
+use rusqlite::Connection;
+use serenity::model::id::UserId;
+
+use crate::types::*;
+use crate::store::{StateStore, StoreError};
+use crate::migrations::{self, FileKind, CURRENT_VERSION};
+
+/// The tables, named after the [`RawState`] field they store, and the [`PortableGaggee`] field with the same data.
+///
+/// Each row is a user ID and that user's data as JSON, like an entry in a state file.
+const TABLES: [(&str, &str); 8] = [
+    ("trusts"         , "trusts"         ),
+    ("gags"           , "gags"           ),
+    ("max_msg_lengths", "max_msg_length" ),
+    ("preserve_markup", "preserve_markup"),
+    ("safewords"      , "safewords"      ),
+    ("gag_defaults"   , "gag_defaults"   ),
+    ("announce_expiry", "announce_expiry"),
+    ("thread_opt_outs", "thread_opt_outs")
+];
+
+/// A [`StateStore`] that keeps the [`State`] in an SQLite database, only updating the rows of users whose data changed.
+///
+/// The database's `user_version` is the [`migrations::CURRENT_VERSION`] of its rows.
+#[derive(Debug)]
+pub struct SqliteStore {
+    /// The connection to the database.
+    connection: Mutex<Connection>
+}
+
+impl SqliteStore {
+    /// Opens or creates the database at `path`.
+    /// # Errors
+    /// If the database can't be opened or its tables can't be made, returns the error [`StoreError::SqliteError`].
+    pub fn open(path: &Path) -> Result<Self, StoreError> {
+        let connection = Connection::open(path)?;
+        for (table, _) in TABLES {
+            connection.execute(&format!("CREATE TABLE IF NOT EXISTS {table} (user TEXT PRIMARY KEY, data TEXT NOT NULL)"), ())?;
+        }
+        Ok(Self {connection: Mutex::new(connection)})
+    }
+}
+
+impl StateStore for SqliteStore {
+    /// Loads the [`State`].
+    ///
+    /// If the database is from an older version, every row is rewritten in the current version.
+    fn load(&self) -> Result<State, StoreError> {
+        let connection = self.connection.lock().expect("No panics");
+        let version = connection.pragma_query_value(None, "user_version", |row| row.get::<_, u64>(0))?;
+        let mut file = serde_json::Map::new();
+        file.insert("version".to_string(), version.into());
+        for (table, _) in TABLES {
+            let mut rows = serde_json::Map::new();
+            let mut statement = connection.prepare(&format!("SELECT user, data FROM {table}"))?;
+            for row in statement.query_map((), |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+                let (user, data) = row?;
+                rows.insert(user, serde_json::from_str(&data)?);
+            }
+            file.insert(table.to_string(), rows.into());
+        }
+        drop(connection);
+
+        let state: State = migrations::migrate(FileKind::State, file.into())?;
+        if version != CURRENT_VERSION {
+            self.save(&state, &state.users())?;
+            self.connection.lock().expect("No panics").pragma_update(None, "user_version", CURRENT_VERSION)?;
+        }
+        Ok(state)
+    }
+
+    fn save(&self, state: &State, changed: &HashSet<UserId>) -> Result<(), StoreError> {
+        let mut connection = self.connection.lock().expect("No panics");
+        let transaction = connection.transaction()?;
+        for user in changed {
+            let data = serde_json::to_value(state.export(*user))?;
+            for (table, field) in TABLES {
+                match data.get(field).filter(|value| !value.is_null()) {
+                    Some(value) => transaction.execute(&format!("INSERT OR REPLACE INTO {table} (user, data) VALUES (?1, ?2)"), (user.to_string(), value.to_string()))?,
+                    None        => transaction.execute(&format!("DELETE FROM {table} WHERE user = ?1"), (user.to_string(),))?
+                };
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let store = SqliteStore::open(Path::new(":memory:")).expect("Opening an in-memory database to work");
+        let state = store.load().expect("Loading an empty database to work");
+        let data = migrations::migrate_str::<PortableGaggee>(FileKind::PortableGaggee, include_str!("../../fixtures/portable_gaggee/v1.json")).expect("The fixture to be valid");
+        state.import(100.into(), data.clone());
+        state.import(101.into(), data.clone());
+        store.save(&state, &state.take_changed_users()).expect("Saving to work");
+
+        state.import(101.into(), PortableGaggee::default());
+        store.save(&state, &state.take_changed_users()).expect("Saving to work");
+
+        let loaded = store.load().expect("Loading to work");
+        assert_eq!(loaded.export(100.into()), data);
+        assert_eq!(loaded.export(101.into()), PortableGaggee::default());
+        assert_eq!(store.connection.lock().expect("No panics").pragma_query_value(None, "user_version", |row| row.get::<_, u64>(0)).expect("The user_version to be readable"), CURRENT_VERSION);
+    }
+}
@@ -2,6 +2,10 @@
 
 mod gag;
 pub use gag::*;
+mod gag_scope;
+pub use gag_scope::*;
+mod gag_duration;
+pub use gag_duration::*;
 mod trust;
 pub use trust::*;
 mod safeword;
@@ -12,7 +16,17 @@ mod member_id;
 pub use member_id::*;
 mod state;
 pub use state::*;
+mod user_state;
+pub use user_state::*;
 mod rewriter;
 pub use rewriter::*;
+mod dictionary_gag_mode;
+pub use dictionary_gag_mode::*;
+mod gag_mode_registry;
+pub use gag_mode_registry::*;
+mod gag_filter;
+pub use gag_filter::*;
+mod script;
+pub use script::*;
 mod portable;
 pub use portable::*;
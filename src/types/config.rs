@@ -7,8 +7,15 @@ use crate::types::*;
 /// What to do to a message.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MessageAction {
-    /// Gag with with the specified [`GagMode`].
-    Gag(GagModeName),
+    /// Gag with with the specified [`GagConfig`].
+    Gag {
+        /// The [`GagConfig`] to gag with.
+        config: GagConfig,
+        /// If [`true`], keep Discord markup like mentions and links as is.
+        preserve_markup: bool,
+        /// If [`Some`], this was the gag's last message, so it was removed and should be [announced](crate::expiry::announce).
+        expired: Option<ExpiredGag>
+    },
     /// Warn that it's too long to gag.
     WarnTooLong(usize)
 }
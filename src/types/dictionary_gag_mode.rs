@@ -0,0 +1,93 @@
+//! Gag modes that replace whole words.
+
+use std::collections::HashMap;
+
+use rand::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::types::*;
+
+/// A gag mode that replaces whole words using a dictionary.
+///
+/// Can be used for speech styles (`hello` -> `ahoy`) or, with [`Self::otherwise`], to only let some words through.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DictionaryGagMode {
+    /// The possible replacements for each word, keyed by the lowercase word.
+    ///
+    /// One is chosen at random each time the word is used.
+    pub words: HashMap<String, Vec<String>>,
+    /// If [`Some`], the [`GagMode::Markov`] to muffle words not in [`Self::words`] with.
+    ///
+    /// If [`None`], words not in [`Self::words`] are kept as is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub otherwise: Option<GagModeName>
+}
+
+impl DictionaryGagMode {
+    /// Returns every [`GagModeProblem`].
+    ///
+    /// `registry` is used to check [`Self::otherwise`].
+    pub fn problems(&self, registry: &GagModeRegistry) -> Vec<GagModeProblem> {
+        let mut ret = Vec::new();
+
+        if let Some(otherwise) = &self.otherwise && !matches!(registry.definition(otherwise).map(|definition| &definition.mode), Some(GagMode::Markov(_))) {
+            ret.push(GagModeProblem::BadOtherwise(otherwise.clone()));
+        }
+
+        let mut words = self.words.iter().collect::<Vec<_>>();
+        words.sort();
+        for (word, replacements) in words {
+            if replacements.is_empty() {ret.push(GagModeProblem::NoReplacements(word.clone()));}
+            if *word != word.to_lowercase() {ret.push(GagModeProblem::NotLowercase(word.clone()));}
+        }
+
+        ret
+    }
+
+    /// Replace every word of `text` that's in [`Self::words`], letting some words slip through depending on `intensity`.
+    ///
+    /// Replacements keep the capitalization of the word they replace.
+    ///
+    /// Words not in [`Self::words`] are handled as described in [`Self::otherwise`], with `word_shape` passed to [`MarkovGagMode::muffle_with_rng`].
+    /// # Errors
+    /// If the call to [`MarkovGagMode::muffle_with_rng`] returns an error, that error is returned.
+    pub fn muffle_with_rng<R: Rng + ?Sized>(&self, text: &str, intensity: Intensity, word_shape: bool, rng: &mut R) -> Result<String, GagModeError> {
+        let otherwise = match self.otherwise.as_ref().and_then(GagModeName::get) {
+            Some(GagMode::Markov(mode)) => Some(mode),
+            _ => None
+        };
+        let mut ret = String::with_capacity(text.len());
+        let mut last = 0;
+        for found in WORD.find_iter(text) {
+            ret.push_str(&text[last..found.start()]);
+            let word = found.as_str();
+            if intensity.leaks(rng) {
+                ret.push_str(word);
+            } else if let Some(replacement) = self.words.get(&word.to_lowercase()).and_then(|replacements| replacements.choose(rng)) {
+                ret.push_str(&cased_like_word(replacement, word));
+            } else if let Some(mode) = otherwise {
+                ret.push_str(&mode.muffle_with_rng(word, Intensity::default(), word_shape, rng)?);
+            } else {
+                ret.push_str(word);
+            }
+            last = found.end();
+        }
+        ret.push_str(&text[last..]);
+        Ok(ret)
+    }
+}
+
+/// Capitalizes `replacement` like `word`.
+///
+/// If `word` is all caps and longer than one letter, `replacement` is uppercased. If `word` starts with a capital letter, so does `replacement`.
+fn cased_like_word(replacement: &str, word: &str) -> String {
+    let mut chars = word.chars();
+    match (chars.next(), chars.next()) {
+        (Some(_), Some(_)) if word.chars().any(char::is_uppercase) && !word.chars().any(char::is_lowercase) => replacement.to_uppercase(),
+        (Some(first), _) if first.is_uppercase() => {
+            let mut replacement_chars = replacement.chars();
+            replacement_chars.next().map(|first| first.to_uppercase().chain(replacement_chars).collect()).unwrap_or_default()
+        },
+        _ => replacement.to_string()
+    }
+}
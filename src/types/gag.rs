@@ -1,10 +1,11 @@
 //! Gags.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeSet};
 
+use rand::Rng;
 use serde::{Serialize, Deserialize};
 use serenity::all::Timestamp;
-use serenity::model::id::{GuildId, ChannelId, UserId};
+use serenity::model::id::{GuildId, UserId};
 
 use crate::types::*;
 use crate::util::*;
@@ -12,8 +13,8 @@ use crate::util::*;
 /// A new [`Gag`] to apply to someone.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct NewGag {
-    /// The [`ChannelId`] to apply [`Self::gag`] in.
-    pub channel: ChannelId,
+    /// The [`GagScope`] to apply [`Self::gag`] in.
+    pub scope: GagScope,
     /// The gag to apply.
     pub gag: Gag
 }
@@ -27,51 +28,214 @@ impl From<NewGag> for Gag {
 /// A new... uh, ungagging?
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct NewUngag {
-    /// The channel to remove the gag from.
-    pub channel: ChannelId
+    /// The [`GagScope`] to remove the gag from.
+    pub scope: GagScope
 }
 
 /// A new tie.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct NewTie {
-    /// The channel to apply the tie in.
-    pub channel: ChannelId
+    /// The [`GagScope`] of the gag to tie.
+    pub scope: GagScope,
+    /// If [`true`], the tier becomes the gag's keyholder. See [`Gag::key`].
+    pub keep_key: bool
 }
 
 /// A new untie.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct NewUntie {
-    /// The channel to remove the tie in.
-    pub channel: ChannelId
+    /// The [`GagScope`] of the gag to untie.
+    pub scope: GagScope
 }
 
+/// Handing the key of a tied [`Gag`] to someone else.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GiveKey {
+    /// The [`GagScope`] of the gag whose key to give.
+    pub scope: GagScope,
+    /// The member to give the key to.
+    pub to: MemberId
+}
+
+/// A change to a [`Gag::until`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeTimer {
+    /// The [`GagScope`] whose gag to change.
+    pub scope: GagScope,
+    /// The seconds to add. Negative to remove time.
+    pub seconds: i64
+}
+
+/// A change to a [`Gag`].
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ChangeGag {
-    /// The [`ChannelId`] whose gag to change.
-    pub channel: ChannelId,
-    /// The [`GagModeName`] to change the gag to.
-    pub mode: GagModeName
+    /// The [`GagScope`] whose gag to change.
+    pub scope: GagScope,
+    /// If [`Some`], the [`GagModeName`] to change the gag to.
+    pub mode: Option<GagModeName>,
+    /// If [`Some`], the [`Intensity`] to change the gag to.
+    pub intensity: Option<Intensity>,
+    /// If [`Some`], what to change [`GagConfig::word_shape`] to.
+    pub word_shape: Option<bool>,
+    /// If [`Some`], the [`GagFilter`]s to change the gag to.
+    pub filters: Option<Vec<GagFilter>>,
+    /// If [`Some`], the allowed words to change the gag to.
+    pub allowed_words: Option<BTreeSet<String>>
 }
 
 /// A gag
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Gag {
     /// The point in time where the gag no longer applies.
     #[serde(default, skip_serializing_if = "is_default")]
     pub until: Option<Timestamp>,
+    /// If [`Some`], the gag is removed after gagging this many more messages.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub messages_left: Option<u32>,
+    /// If [`Some`], the gag is removed once it's gagged this many more words.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub words_left: Option<u32>,
+    /// If [`Some`], only the keyholder can untie the gag.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub key: Option<GagKey>,
     /// The config of a [`Gag`].
     #[serde(flatten)]
     pub config: GagConfig
 }
 
+/// The key to a tied [`Gag`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GagKey {
+    /// The member who holds the key.
+    pub holder: MemberId,
+    /// Who held the key before [`Self::holder`], oldest first, starting with whoever tied the gag.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub previous: Vec<MemberId>
+}
+
+impl GagKey {
+    /// Makes a [`Self`] held by `holder`.
+    pub fn new(holder: MemberId) -> Self {
+        Self {holder, previous: Vec::new()}
+    }
+
+    /// Hands the key to `to`, recording the current holder in [`Self::previous`].
+    pub fn give(&mut self, to: MemberId) {
+        self.previous.push(std::mem::replace(&mut self.holder, to));
+    }
+}
+
 /// The config of a [`Gag`].
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct GagConfig {
-    /// If [`true`], the [`Gaggee`] can't ungag themself and anyone trying to ungag them needs [`Trust::untie`] consent.
+    /// If [`true`], the [`Gaggee`] can't ungag themself and anyone trying to ungag them needs [`Trust::untie`] consent and, if the gag has a [`Gag::key`], to hold it.
     #[serde(default)]
     pub tie: bool,
     /// The [`GagModeName`] this gag uses.
-    pub mode: GagModeName
+    pub mode: GagModeName,
+    /// How muffled messages are.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub intensity: Intensity,
+    /// If [`true`], muffled words keep their length, syllables, and capitalization.
+    ///
+    /// See [`RewriteOptions::word_shape`].
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub word_shape: bool,
+    /// The [`GagFilter`]s to apply, in order.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub filters: Vec<GagFilter>,
+    /// Lowercase words that are never muffled, like safewords or names.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub allowed_words: BTreeSet<String>
+}
+
+impl Gag {
+    /// Whether `gagger` is allowed to untie the gag as far as [`Self::key`] is concerned.
+    ///
+    /// Keys only work in the server they were given in. [`Trust::untie`] is still needed.
+    pub fn unlockable_by(&self, gagger: MemberId) -> bool {
+        self.key.as_ref().is_none_or(|key| key.holder == gagger)
+    }
+
+    /// Counts down [`Self::messages_left`] and [`Self::words_left`] for a gagged message with `words` words.
+    ///
+    /// Returns [`true`] if either ran out, so the gag should be removed.
+    pub fn count_down(&mut self, words: usize) -> bool {
+        if let Some(messages_left) = &mut self.messages_left {*messages_left = messages_left.saturating_sub(1);}
+        if let Some(words_left) = &mut self.words_left {*words_left = words_left.saturating_sub(words.try_into().unwrap_or(u32::MAX));}
+        self.messages_left == Some(0) || self.words_left == Some(0)
+    }
+
+    /// Describes how many messages and words are left before the gag is removed, like "3 more messages or 20 more words".
+    ///
+    /// If the gag isn't limited by either, returns [`None`].
+    pub fn describe_left(&self) -> Option<String> {
+        let messages = self.messages_left.map(|n| if n == 1 {"1 more message".to_string()} else {format!("{n} more messages")});
+        let words    = self.words_left   .map(|n| if n == 1 {"1 more word"   .to_string()} else {format!("{n} more words"   )});
+        match (messages, words) {
+            (Some(messages), Some(words)) => Some(format!("{messages} or {words}")),
+            (messages, words) => messages.or(words)
+        }
+    }
+}
+
+impl GagConfig {
+    /// The [`RewriteOptions`] to rewrite messages with.
+    pub fn rewrite_options(&self) -> RewriteOptions<'_> {
+        RewriteOptions {
+            intensity    : self.intensity,
+            word_shape   : self.word_shape,
+            filters      : &self.filters,
+            allowed_words: &self.allowed_words
+        }
+    }
+}
+
+/// How much of a message a [`Gag`] muffles, from 0% to 100%.
+///
+/// Each word has a `100 - intensity` percent chance to slip through unmuffled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
+pub struct Intensity(u8);
+
+impl Intensity {
+    /// Makes a [`Self`], capping `percent` at 100.
+    pub fn new(percent: u8) -> Self {
+        Self(percent.min(100))
+    }
+
+    /// Randomly decide if a word slips through unmuffled.
+    ///
+    /// Doesn't use `rng` when the intensity is 100%, so fully muffling never changes the output of a seeded [`Rng`].
+    pub fn leaks<R: Rng + ?Sized>(self, rng: &mut R) -> bool {
+        self.0 < 100 && !rng.random_ratio(self.0.into(), 100)
+    }
+}
+
+impl From<u8> for Intensity {
+    /// Caps `percent` at 100, so out of range values in state files and imports can't get in.
+    fn from(percent: u8) -> Self {
+        Self::new(percent)
+    }
+}
+
+impl From<Intensity> for u8 {
+    fn from(value: Intensity) -> Self {
+        value.0
+    }
+}
+
+impl Default for Intensity {
+    /// 100%.
+    fn default() -> Self {
+        Self(100)
+    }
+}
+
+impl std::fmt::Display for Intensity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}%", self.0)
+    }
 }
 
 /// Configures the default values for gags.
@@ -104,20 +268,82 @@ impl GagDefaults {
 }
 
 /// Overrides for [`GagConfig`]s.
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct GagConfigDiff {
     /// If [`Some`], overwrites [`GagConfig::tie`].
     #[serde(default, skip_serializing_if = "is_default")]
     pub tie: Option<bool>,
     /// If [`Some`], overwrites [`GagConfig::mode`].
     #[serde(default, skip_serializing_if = "is_default")]
-    pub mode: Option<GagModeName>
+    pub mode: Option<GagModeName>,
+    /// If [`Some`], overwrites [`GagConfig::intensity`].
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub intensity: Option<Intensity>,
+    /// If [`Some`], overwrites [`GagConfig::word_shape`].
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub word_shape: Option<bool>,
+    /// If [`Some`], overwrites [`GagConfig::filters`].
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub filters: Option<Vec<GagFilter>>,
+    /// If [`Some`], overwrites [`GagConfig::allowed_words`].
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub allowed_words: Option<BTreeSet<String>>
 }
 
 impl GagConfigDiff {
     /// Apply the diffs.
     pub fn apply(&self, to: &mut GagConfig) {
         if let Some(tie ) = self.tie  {to.tie  = tie ;}
-        if let Some(mode) = self.mode {to.mode = mode;}
+        if let Some(mode) = &self.mode {to.mode = mode.clone();}
+        if let Some(intensity    ) = self.intensity      {to.intensity     = intensity            ;}
+        if let Some(word_shape   ) = self.word_shape     {to.word_shape    = word_shape           ;}
+        if let Some(filters      ) = &self.filters       {to.filters       = filters.clone()      ;}
+        if let Some(allowed_words) = &self.allowed_words {to.allowed_words = allowed_words.clone();}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counting_down() {
+        let mut gag = Gag {messages_left: Some(2), words_left: Some(10), ..Default::default()};
+        assert_eq!(gag.describe_left().as_deref(), Some("2 more messages or 10 more words"));
+        assert!(!gag.count_down(3));
+        assert_eq!(gag.describe_left().as_deref(), Some("1 more message or 7 more words"));
+        assert!(gag.count_down(0));
+        let mut gag = Gag {words_left: Some(5), ..Default::default()};
+        assert!(gag.count_down(50));
+        assert_eq!(gag.words_left, Some(0));
+        let mut gag = Gag::default();
+        assert!(!gag.count_down(50));
+        assert_eq!(gag.describe_left(), None);
+        // Only the 2 words outside the code block and link are gagged.
+        let mut gag = Gag {words_left: Some(3), ..Default::default()};
+        assert!(!gag.count_down(crate::markup::gagged_word_count("see ```\nsome code here\n``` and https://example.com/lots/of/words", true)));
+        assert_eq!(gag.words_left, Some(1));
+    }
+
+    #[test]
+    fn keys() {
+        let member = |user: u64| MemberId {guild: 1.into(), user: user.into()};
+        let mut gag = Gag::default();
+        assert!(gag.unlockable_by(member(2)));
+        gag.key = Some(GagKey::new(member(2)));
+        assert!( gag.unlockable_by(member(2)));
+        assert!(!gag.unlockable_by(member(3)));
+        gag.key.as_mut().expect("The gag to have a key").give(member(3));
+        assert!(!gag.unlockable_by(member(2)));
+        assert!( gag.unlockable_by(member(3)));
+        assert!(!gag.unlockable_by(MemberId {guild: 4.into(), ..member(3)}));
+        assert_eq!(gag.key, Some(GagKey {holder: member(3), previous: vec![member(2)]}));
+    }
+
+    #[test]
+    fn intensity() {
+        assert_eq!(serde_json::from_str::<Intensity>("50" ).expect("50 to be a valid intensity"), Intensity::new(50));
+        assert_eq!(serde_json::from_str::<Intensity>("250").expect("250 to be a valid intensity"), Intensity::new(100));
+        assert_eq!(serde_json::to_string(&Intensity::new(50)).expect("Serialization to never fail"), "50");
     }
 }
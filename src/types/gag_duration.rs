@@ -0,0 +1,105 @@
+//! How long [`Gag`]s last.
+
+use std::str::FromStr;
+
+use serenity::model::timestamp::Timestamp;
+use thiserror::Error;
+
+/// How long a [`Gag`] lasts, or how much to change its timer by.
+///
+/// Parsed from durations like `1h30m`, `2d`, or `90` (minutes), or from Discord timestamps like `<t:1700000000>` or `<t:1700000000:R>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GagDuration {
+    /// For a number of seconds.
+    For(u64),
+    /// Until a point in time.
+    Until(Timestamp)
+}
+
+/// The enum of errors parsing a [`GagDuration`] can return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum GagDurationError {
+    /// Returned when the text isn't a duration or timestamp.
+    #[error("Expected a duration like `1h30m` or `2d`, or a timestamp like `<t:1700000000>`.")]
+    Invalid,
+    /// Returned when the duration or timestamp is too far in the future.
+    #[error("That's too long.")]
+    TooLong
+}
+
+/// The amount of seconds in each unit a duration can use.
+const UNITS: [(char, u64); 5] = [
+    ('w', 7 * 24 * 60 * 60),
+    ('d',     24 * 60 * 60),
+    ('h',          60 * 60),
+    ('m',               60),
+    ('s',                1)
+];
+
+impl FromStr for GagDuration {
+    type Err = GagDurationError;
+
+    /// A bare number is a number of minutes.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(timestamp) = s.strip_prefix("<t:").and_then(|s| s.strip_suffix('>')) {
+            let seconds = timestamp.split(':').next().and_then(|seconds| seconds.parse::<i64>().ok()).ok_or(GagDurationError::Invalid)?;
+            return Ok(Self::Until(Timestamp::from_unix_timestamp(seconds).map_err(|_| GagDurationError::TooLong)?));
+        }
+        if let Ok(minutes) = s.parse::<u64>() {
+            return Ok(Self::For(minutes.checked_mul(60).ok_or(GagDurationError::TooLong)?));
+        }
+
+        let mut total = 0u64;
+        let mut number = None::<u64>;
+        for c in s.chars().filter(|c| !c.is_whitespace()) {
+            if let Some(digit) = c.to_digit(10) {
+                number = Some(number.unwrap_or_default().checked_mul(10).and_then(|number| number.checked_add(digit.into())).ok_or(GagDurationError::TooLong)?);
+            } else {
+                let (_, unit) = UNITS.iter().find(|(name, _)| *name == c.to_ascii_lowercase()).ok_or(GagDurationError::Invalid)?;
+                let seconds = number.take().ok_or(GagDurationError::Invalid)?.checked_mul(*unit).ok_or(GagDurationError::TooLong)?;
+                total = total.checked_add(seconds).ok_or(GagDurationError::TooLong)?;
+            }
+        }
+        if number.is_some() || s.is_empty() {Err(GagDurationError::Invalid)?}
+        Ok(Self::For(total))
+    }
+}
+
+impl GagDuration {
+    /// When a [`Gag`] made at `now` with this duration ends.
+    /// # Errors
+    /// If that's too far in the future to be a [`Timestamp`], returns the error [`GagDurationError::TooLong`].
+    pub fn end(self, now: Timestamp) -> Result<Timestamp, GagDurationError> {
+        match self {
+            Self::For(seconds) => add_seconds(now, seconds.try_into().map_err(|_| GagDurationError::TooLong)?),
+            Self::Until(until) => Ok(until)
+        }
+    }
+}
+
+/// Adds `seconds`, which can be negative, to `timestamp`.
+/// # Errors
+/// If the result isn't a valid [`Timestamp`], returns the error [`GagDurationError::TooLong`].
+pub fn add_seconds(timestamp: Timestamp, seconds: i64) -> Result<Timestamp, GagDurationError> {
+    Timestamp::from_unix_timestamp(timestamp.unix_timestamp().checked_add(seconds).ok_or(GagDurationError::TooLong)?).map_err(|_| GagDurationError::TooLong)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        assert_eq!("90"    .parse(), Ok(GagDuration::For(90 * 60)));
+        assert_eq!("1h30m" .parse(), Ok(GagDuration::For(90 * 60)));
+        assert_eq!("2d"    .parse(), Ok(GagDuration::For(2 * 24 * 60 * 60)));
+        assert_eq!("1W 1s" .parse(), Ok(GagDuration::For(7 * 24 * 60 * 60 + 1)));
+        assert_eq!("<t:1700000000:R>".parse(), Ok(GagDuration::Until(Timestamp::from_unix_timestamp(1700000000).expect("The test time to be valid"))));
+        for invalid in ["", "h", "1x", "1h30", "<t:soon>"] {
+            assert_eq!(invalid.parse::<GagDuration>(), Err(GagDurationError::Invalid), "{invalid}");
+        }
+        assert_eq!("99999999999999999999d".parse::<GagDuration>(), Err(GagDurationError::TooLong));
+        assert_eq!(GagDuration::For(u64::MAX).end(Timestamp::now()), Err(GagDurationError::TooLong));
+    }
+}
@@ -0,0 +1,144 @@
+//! Filters that can be stacked on top of a [`GagMode`].
+
+use std::str::FromStr;
+
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
+
+/// A filter that changes text before or after it's muffled by a [`GagMode`].
+///
+/// A [`GagConfig`] can have any number of filters, which are applied in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum GagFilter {
+    /// Sometimes repeats the first letter of words, like `h-hello`.
+    Stutter,
+    /// Turns `s` and `z` into `th`.
+    Lisp,
+    /// Turns `r` and `l` into `w` and `na` into `nya`.
+    Uwu,
+    /// Lowercases messages and makes them subtext.
+    Whisper,
+    /// Uppercases messages.
+    Shout
+}
+
+/// When a [`GagFilter`] is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterStage {
+    /// Before the [`GagMode`], so the filter's changes get muffled.
+    Pre,
+    /// After the [`GagMode`], so the filter changes the muffles.
+    Post
+}
+
+impl GagFilter {
+    /// All [`GagFilter`]s.
+    pub const ALL: [Self; 5] = [Self::Stutter, Self::Lisp, Self::Uwu, Self::Whisper, Self::Shout];
+
+    /// When the filter is applied.
+    pub fn stage(self) -> FilterStage {
+        match self {
+            Self::Stutter | Self::Lisp | Self::Uwu => FilterStage::Pre,
+            Self::Whisper | Self::Shout            => FilterStage::Post
+        }
+    }
+
+    /// Apply the filter to text using the specified [`Rng`].
+    ///
+    /// [`Self::Whisper`] only lowercases the text. Making it subtext is done by [`crate::markup::subtext`] on the whole message.
+    pub fn apply_with_rng<R: Rng + ?Sized>(self, text: &str, rng: &mut R) -> String {
+        let mut ret = String::with_capacity(text.len());
+        let mut chars = text.chars().peekable();
+        let mut prev = None::<char>;
+        while let Some(c) = chars.next() {
+            match self {
+                Self::Stutter => {
+                    if c.is_alphabetic() && !prev.is_some_and(char::is_alphabetic) && rng.random_ratio(1, 3) {ret.push(c); ret.push('-');}
+                    ret.push(c);
+                },
+                Self::Lisp => match c {
+                    's' | 'z' => ret.push_str("th"),
+                    'S' | 'Z' => ret.push_str(if chars.peek().is_some_and(|next| next.is_uppercase()) || prev.is_some_and(char::is_uppercase) {"TH"} else {"Th"}),
+                    _ => ret.push(c)
+                },
+                Self::Uwu => match c {
+                    'r' | 'l' => ret.push('w'),
+                    'R' | 'L' => ret.push('W'),
+                    'n' | 'N' => {
+                        ret.push(c);
+                        if let Some(next) = chars.peek() && "aeiouAEIOU".contains(*next) {ret.push(if next.is_uppercase() {'Y'} else {'y'});}
+                    },
+                    _ => ret.push(c)
+                },
+                Self::Whisper => ret.extend(c.to_lowercase()),
+                Self::Shout   => ret.extend(c.to_uppercase())
+            }
+            prev = Some(c);
+        }
+        ret
+    }
+}
+
+/// Unknown [`GagFilter`].
+#[derive(Debug, Error)]
+#[error("Unknown GagFilter")]
+pub struct UnknownGagFilter;
+
+impl FromStr for GagFilter {
+    type Err = UnknownGagFilter;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|filter| filter.to_string() == s).ok_or(UnknownGagFilter)
+    }
+}
+
+impl std::fmt::Display for GagFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.write_str(match self {
+            Self::Stutter => "Stutter",
+            Self::Lisp    => "Lisp",
+            Self::Uwu     => "Uwu",
+            Self::Whisper => "Whisper",
+            Self::Shout   => "Shout"
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    /// Applies a [`GagFilter`] with a [`ChaCha8Rng`] seeded with 1.
+    fn apply(filter: GagFilter, text: &str) -> String {
+        filter.apply_with_rng(text, &mut ChaCha8Rng::seed_from_u64(1))
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(apply(GagFilter::Lisp   , "Sassy ZEBRAS, Sir"), "Thaththy THEBRATH, Thir");
+        assert_eq!(apply(GagFilter::Uwu    , "Really nice NAP"  ), "Weawwy nyice NYAP");
+        assert_eq!(apply(GagFilter::Whisper, "Hello THERE"      ), "hello there");
+        assert_eq!(apply(GagFilter::Shout  , "Hello there"      ), "HELLO THERE");
+    }
+
+    #[test]
+    fn stutter() {
+        let text = "the quick brown fox jumps over the lazy dog";
+        let output = apply(GagFilter::Stutter, text);
+        assert_ne!(output, text);
+        for (stuttered, word) in output.split(' ').zip(text.split(' ')) {
+            assert!(stuttered == word || stuttered == format!("{}-{word}", &word[..1]), "{stuttered}");
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for filter in GagFilter::ALL {
+            assert_eq!(filter.to_string().parse::<GagFilter>().ok(), Some(filter));
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! The set of [`GagMode`]s the bot knows about, loaded from gag mode files.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
+
+use crate::types::*;
+
+/// The built-in gag mode files, embedded into the binary.
+///
+/// Each is `(name, contents)`, where the name is the file's stem.
+pub const BUILTIN_GAG_MODES: [(&str, &str); 11] = [
+    ("Gag"   , include_str!("../../gag_modes/Gag.json"   )),
+    ("Sock"  , include_str!("../../gag_modes/Sock.json"  )),
+    ("Dog"   , include_str!("../../gag_modes/Dog.json"   )),
+    ("Puppy" , include_str!("../../gag_modes/Puppy.json" )),
+    ("Cow"   , include_str!("../../gag_modes/Cow.json"   )),
+    ("Fox"   , include_str!("../../gag_modes/Fox.json"   )),
+    ("Cat"   , include_str!("../../gag_modes/Cat.json"   )),
+    ("Seal"  , include_str!("../../gag_modes/Seal.json"  )),
+    ("Bee"   , include_str!("../../gag_modes/Bee.json"   )),
+    ("Pirate", include_str!("../../gag_modes/Pirate.json")),
+    ("Muzzle", include_str!("../../gag_modes/Muzzle.json"))
+];
+
+/// The contents of a gag mode file.
+///
+/// The name of the mode is the file's stem, so `Gag.json` defines the [`GagModeName`] `Gag`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GagModeDefinition {
+    /// The name to show to users.
+    pub display_name: String,
+    /// The icon. Usually an emoji.
+    pub icon: String,
+    /// The actual [`GagMode`].
+    #[serde(flatten)]
+    pub mode: GagMode
+}
+
+/// The set of [`GagMode`]s the bot knows about.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GagModeRegistry {
+    /// The [`GagModeDefinition`]s.
+    modes: HashMap<GagModeName, GagModeDefinition>
+}
+
+/// The enum of errors [`GagModeRegistry::load_dir`] can return.
+#[derive(Debug, Error)]
+pub enum GagModeLoadError {
+    /// Returned when an [`std::io::Error`] is encountered.
+    #[error("{0:?}: {1}")]
+    IoError(PathBuf, std::io::Error),
+    /// Returned when a gag mode file is invalid.
+    #[error("{0:?}: {1}")]
+    InvalidGagMode(PathBuf, serde_json::Error),
+    /// Returned when a gag mode file's name isn't valid UTF-8.
+    #[error("{0:?}: The file name isn't valid UTF-8.")]
+    InvalidFileName(PathBuf)
+}
+
+/// The [`GagModeRegistry`] used by the whole bot.
+static GAG_MODE_REGISTRY: OnceLock<GagModeRegistry> = OnceLock::new();
+
+impl GagModeRegistry {
+    /// Makes a [`Self`] with just the [`BUILTIN_GAG_MODES`].
+    /// # Panics
+    /// If any of the [`BUILTIN_GAG_MODES`] are invalid, panics.
+    pub fn builtin() -> Self {
+        Self {
+            modes: BUILTIN_GAG_MODES.into_iter()
+                .map(|(name, contents)| (GagModeName(name.to_string()), serde_json::from_str(contents).expect("The built-in gag modes to be valid")))
+                .collect()
+        }
+    }
+
+    /// Loads every `.json` file in `dir` as a gag mode, overwriting any existing modes with the same name.
+    /// # Errors
+    /// If reading the directory or any of its files fails, returns the error [`GagModeLoadError::IoError`].
+    ///
+    /// If a file isn't a valid [`GagModeDefinition`], returns the error [`GagModeLoadError::InvalidGagMode`].
+    ///
+    /// If a file's stem isn't valid UTF-8, returns the error [`GagModeLoadError::InvalidFileName`].
+    pub fn load_dir(&mut self, dir: &Path) -> Result<(), GagModeLoadError> {
+        for entry in std::fs::read_dir(dir).map_err(|e| GagModeLoadError::IoError(dir.to_path_buf(), e))? {
+            let path = entry.map_err(|e| GagModeLoadError::IoError(dir.to_path_buf(), e))?.path();
+            if path.extension().is_none_or(|extension| extension != "json") {continue;}
+
+            let name = path.file_stem().and_then(|stem| stem.to_str()).ok_or_else(|| GagModeLoadError::InvalidFileName(path.clone()))?.to_string();
+            let contents = std::fs::read_to_string(&path).map_err(|e| GagModeLoadError::IoError(path.clone(), e))?;
+            let definition = serde_json::from_str(&contents).map_err(|e| GagModeLoadError::InvalidGagMode(path.clone(), e))?;
+
+            self.modes.insert(GagModeName(name), definition);
+        }
+        Ok(())
+    }
+
+    /// Makes this the [`GagModeRegistry`] used by the whole bot.
+    /// # Errors
+    /// If the bot's [`GagModeRegistry`] was already set or used, returns [`Self`].
+    pub fn install(self) -> Result<(), Self> {
+        GAG_MODE_REGISTRY.set(self)
+    }
+
+    /// Gets the [`GagModeRegistry`] used by the whole bot.
+    ///
+    /// If [`Self::install`] wasn't called, installs [`Self::builtin`].
+    pub fn get() -> &'static Self {
+        GAG_MODE_REGISTRY.get_or_init(Self::builtin)
+    }
+
+    /// Gets a [`GagModeDefinition`].
+    pub fn definition(&self, name: &GagModeName) -> Option<&GagModeDefinition> {
+        self.modes.get(name)
+    }
+
+    /// Gets the [`GagModeProblem`]s of every gag mode that has any, sorted by name.
+    pub fn problems(&self) -> Vec<(&GagModeName, Vec<GagModeProblem>)> {
+        let mut ret = self.modes.iter()
+            .map(|(name, definition)| (name, definition.mode.problems(self)))
+            .filter(|(_, problems)| !problems.is_empty())
+            .collect::<Vec<_>>();
+        ret.sort_by_key(|(name, _)| *name);
+        ret
+    }
+
+    /// An iterator over all the [`GagModeName`]s.
+    pub fn names(&self) -> impl Iterator<Item = &GagModeName> {
+        self.modes.keys()
+    }
+}
@@ -0,0 +1,177 @@
+//! Where [`Gag`]s apply.
+
+use std::str::FromStr;
+
+use serde::{Serialize, Deserialize, ser::Serializer, de::{Deserializer, Visitor}};
+use serenity::model::id::{GuildId, ChannelId};
+
+/// Where a [`Gag`] applies.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum GagScope {
+    /// Only in a channel.
+    Channel(ChannelId),
+    /// In every channel in a category.
+    Category(ChannelId),
+    /// In every channel in a server.
+    Server(GuildId),
+    /// Everywhere.
+    Global
+}
+
+impl GagScope {
+    /// The [`GagScopeKind`] of this scope.
+    pub fn kind(self) -> GagScopeKind {
+        match self {
+            Self::Channel (_) => GagScopeKind::Channel,
+            Self::Category(_) => GagScopeKind::Category,
+            Self::Server  (_) => GagScopeKind::Server,
+            Self::Global      => GagScopeKind::Global
+        }
+    }
+}
+
+impl Serialize for GagScope {
+    /// Serializes as `channel:{id}`, `category:{id}`, `server:{id}`, or `global`.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&match self {
+            Self::Channel (channel ) => format!("channel:{channel}"),
+            Self::Category(category) => format!("category:{category}"),
+            Self::Server  (server  ) => format!("server:{server}"),
+            Self::Global             => "global".to_string()
+        })
+    }
+}
+
+/// [`Visitor`] for deserializing [`GagScope`]s.
+struct GagScopeVisitor;
+
+impl Visitor<'_> for GagScopeVisitor {
+    type Value = GagScope;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("Expected \"channel:id\", \"category:id\", \"server:id\", or \"global\"")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        let error = || E::custom("Expected \"channel:id\", \"category:id\", \"server:id\", or \"global\"");
+        match value.split_once(':') {
+            None if value == "global" => Ok(GagScope::Global),
+            Some(("channel" , id)) => Ok(GagScope::Channel (FromStr::from_str(id).map_err(|_| error())?)),
+            Some(("category", id)) => Ok(GagScope::Category(FromStr::from_str(id).map_err(|_| error())?)),
+            Some(("server"  , id)) => Ok(GagScope::Server  (FromStr::from_str(id).map_err(|_| error())?)),
+            _ => Err(error())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for GagScope {
+    /// Deserializes from the format described in [`GagScope::serialize`].
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(GagScopeVisitor)
+    }
+}
+
+/// Command parameter to choose the [`GagScope`] to gag, ungag, tie, untie, change, or get the status of.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, poise::ChoiceParameter)]
+pub enum GagScopeKind {
+    /// [`GagScope::Channel`] with the current channel.
+    #[default]
+    Channel,
+    /// [`GagScope::Category`] with the current channel's category.
+    Category,
+    /// [`GagScope::Server`] with the current server.
+    Server,
+    /// [`GagScope::Global`].
+    Global
+}
+
+impl GagScopeKind {
+    /// Describes the scope from the point of view of a message in it, like "in this channel".
+    pub fn here(self) -> &'static str {
+        match self {
+            Self::Channel  => "in this channel",
+            Self::Category => "in this category",
+            Self::Server   => "in this server",
+            Self::Global   => "everywhere"
+        }
+    }
+}
+
+/// The enum of errors [`GagLocation::scope`] can return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GagScopeError {
+    /// Tried to use [`GagScopeKind::Category`] in a channel that isn't in a category.
+    NotInCategory,
+    /// Tried to use [`GagScopeKind::Server`] outside a server.
+    NotInServer
+}
+
+/// Where a message was sent, for finding the [`GagScope`]s that apply to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GagLocation {
+    /// The channel, or thread or forum post.
+    pub channel: ChannelId,
+    /// If [`Self::channel`] is a thread or forum post, the channel it's in.
+    pub parent: Option<ChannelId>,
+    /// The category of the channel, or of [`Self::parent`] for threads, if it has one.
+    pub category: Option<ChannelId>,
+    /// The server, if the channel is in one.
+    pub server: Option<GuildId>
+}
+
+impl GagLocation {
+    /// The [`GagScope`]s that apply here, most specific first.
+    ///
+    /// In threads, gags on the thread itself come before gags on [`Self::parent`].
+    pub fn scopes(self) -> impl Iterator<Item = GagScope> {
+        [
+            Some(GagScope::Channel(self.channel)),
+            self.parent.map(GagScope::Channel),
+            self.category.map(GagScope::Category),
+            self.server.map(GagScope::Server),
+            Some(GagScope::Global)
+        ].into_iter().flatten()
+    }
+
+    /// The [`GagScope`] of kind `kind` that applies here.
+    /// # Errors
+    /// If `kind` is [`GagScopeKind::Category`] and [`Self::category`] is [`None`], returns the error [`GagScopeError::NotInCategory`].
+    ///
+    /// If `kind` is [`GagScopeKind::Server`] and [`Self::server`] is [`None`], returns the error [`GagScopeError::NotInServer`].
+    pub fn scope(self, kind: GagScopeKind) -> Result<GagScope, GagScopeError> {
+        Ok(match kind {
+            GagScopeKind::Channel  => GagScope::Channel(self.channel),
+            GagScopeKind::Category => GagScope::Category(self.category.ok_or(GagScopeError::NotInCategory)?),
+            GagScopeKind::Server   => GagScope::Server(self.server.ok_or(GagScopeError::NotInServer)?),
+            GagScopeKind::Global   => GagScope::Global
+        })
+    }
+
+    /// Describes where `scope` is from the point of view of a message here, like "in this channel".
+    pub fn here(self, scope: GagScope) -> &'static str {
+        match scope {
+            GagScope::Channel(channel) if Some(channel) == self.parent => "in this thread's channel",
+            _ => scope.kind().here()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scopes() {
+        let location = GagLocation {channel: 1.into(), parent: None, category: Some(2.into()), server: Some(3.into())};
+        assert_eq!(location.scopes().collect::<Vec<_>>(), [GagScope::Channel(1.into()), GagScope::Category(2.into()), GagScope::Server(3.into()), GagScope::Global]);
+        let thread = GagLocation {channel: 4.into(), parent: Some(1.into()), ..location};
+        assert_eq!(thread.scopes().take(2).collect::<Vec<_>>(), [GagScope::Channel(4.into()), GagScope::Channel(1.into())]);
+        assert_eq!(thread.here(GagScope::Channel(1.into())), "in this thread's channel");
+        assert_eq!(GagLocation {category: None, ..location}.scope(GagScopeKind::Category), Err(GagScopeError::NotInCategory));
+        for scope in location.scopes() {
+            let json = serde_json::to_string(&scope).expect("Serialization to never fail");
+            assert_eq!(serde_json::from_str::<GagScope>(&json).expect("The scope to round trip"), scope);
+        }
+        assert!(serde_json::from_str::<GagScope>("\"300\"").is_err());
+    }
+}
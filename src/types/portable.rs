@@ -1,6 +1,6 @@
 //! A representation of a gaggee that can be exported and imported between bot instances.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use serde::{Serialize, Deserialize};
 use serenity::model::id::ChannelId;
@@ -10,19 +10,28 @@ use crate::types::*;
 /// A representation of a gaggee that can be exported and imported between bot instances.
 #[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PortableGaggee {
-    /// [`State::trusts`].
+    /// [`UserState::trusts`].
     #[serde(default)]
     pub trusts: Option<GaggeeTrust>,
-    /// [`State::gags`].
+    /// [`UserState::gags`].
     #[serde(default)]
-    pub gags: Option<HashMap<ChannelId, Gag>>,
-    /// [`State::max_msg_lengths`].
+    pub gags: Option<HashMap<GagScope, Gag>>,
+    /// [`UserState::max_msg_length`].
     #[serde(default)]
     pub max_msg_length: Option<usize>,
-    /// [`State::safewords`].
+    /// [`UserState::preserve_markup`].
+    #[serde(default)]
+    pub preserve_markup: Option<bool>,
+    /// [`UserState::safewords`].
     #[serde(default)]
     pub safewords: Option<Safewords>,
-    /// [`State::gag_defaults`],
+    /// [`UserState::gag_defaults`].
+    #[serde(default)]
+    pub gag_defaults: Option<GagDefaults>,
+    /// [`UserState::announce_expiry`].
+    #[serde(default)]
+    pub announce_expiry: Option<bool>,
+    /// [`UserState::thread_opt_outs`].
     #[serde(default)]
-    pub gag_defaults: Option<GagDefaults>
+    pub thread_opt_outs: Option<HashSet<ChannelId>>
 }
@@ -1,72 +1,61 @@
 //! The code to rewrite/gag messages.
 
 use std::str::FromStr;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, BTreeSet};
+use std::sync::LazyLock;
 
 use rand::prelude::*;
 use rand::distr::weighted::*;
-use serde::{Serialize, Deserialize};
+use regex::Regex;
+use serde::{Serialize, Deserialize, Deserializer};
 use thiserror::Error;
-use poise::ChoiceParameter;
-
-/// The name of a [`GagMode`].
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ChoiceParameter)]
-pub enum GagModeName {
-    /// A gag.
-    #[default]
-    Gag,
-    /// Makes you sound like [`Self::Gag`].
-    Sock,
-    /// Makes you sound like a dog.
-    Dog,
-    /// Makes you sound like [`Self::Dog`].
-    Puppy,
-    /// Makes you sound like a cow.
-    Cow,
-    /// Makes you sound like a fox.
-    Fox,
-    /// Makes you sound like a cat.
-    Cat,
-    /// Makes you sound like a seal.
-    Seal,
-    /// Makes you sound like a bee.
-    Bee
+
+use crate::types::*;
+
+/// The name of a [`GagMode`] in the [`GagModeRegistry`].
+///
+/// Deserializing doesn't check the name is registered so that removing a gag mode doesn't make the state unloadable.
+///
+/// [`FromStr`] does check.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct GagModeName(pub String);
+
+impl Default for GagModeName {
+    /// `Gag`, which is always a built-in.
+    fn default() -> Self {
+        Self("Gag".to_string())
+    }
 }
 
 impl GagModeName {
-    /// A [`HashSet`] with all [`GagModeName`]s.
+    /// A [`HashSet`] with all registered [`GagModeName`]s.
     pub fn all() -> HashSet<Self> {
-        Self::list().into_iter().map(|x| Self::from_name(&x.name).expect("ChoiceParameter to be implemented correctly")).collect()
+        GagModeRegistry::get().names().cloned().collect()
+    }
+
+    /// Gets the [`GagModeDefinition`], if it's registered.
+    pub fn definition(&self) -> Option<&'static GagModeDefinition> {
+        GagModeRegistry::get().definition(self)
     }
 
     /// The icon of a [`GagModeName`]. Usually an emoji.
+    ///
+    /// If the mode isn't registered, returns `❔`.
     pub fn icon(&self) -> &'static str {
-        match self {
-            Self::Gag   => "🔴",
-            Self::Sock  => "🧦",
-            Self::Dog   => "🐶",
-            Self::Puppy => "🐶",
-            Self::Cow   => "🐮",
-            Self::Fox   => "🦊",
-            Self::Cat   => "🐱",
-            Self::Seal  => "🦭",
-            Self::Bee   => "🐝"
-        }
+        self.definition().map(|definition| &*definition.icon).unwrap_or("❔")
     }
 
-    /// Gets the [`GagMode`].
-    pub fn get(&self) -> &'static GagMode {
-        match self {
-            Self::Gag   => &GAG_GAGMODE,
-            Self::Sock  => &GAG_GAGMODE,
-            Self::Dog   => &DOG_GAGMODE,
-            Self::Puppy => &DOG_GAGMODE,
-            Self::Cow   => &COW_GAGMODE,
-            Self::Fox   => &FOX_GAGMODE,
-            Self::Cat   => &CAT_GAGMODE,
-            Self::Seal  => &SEAL_GAGMODE,
-            Self::Bee   => &BEE_GAGMODE
-        }
+    /// The display name of a [`GagModeName`].
+    ///
+    /// If the mode isn't registered, returns the name itself.
+    pub fn display_name(&self) -> &str {
+        self.definition().map(|definition| &*definition.display_name).unwrap_or(&self.0)
+    }
+
+    /// Gets the [`GagMode`], if it's registered.
+    pub fn get(&self) -> Option<&'static GagMode> {
+        self.definition().map(|definition| &definition.mode)
     }
 }
 
@@ -79,29 +68,213 @@ impl FromStr for GagModeName {
     type Err = UnknownGagModeName;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Self::from_name(s).ok_or(UnknownGagModeName)
+        let ret = Self(s.to_string());
+        match ret.definition() {
+            Some(_) => Ok(ret),
+            None => Err(UnknownGagModeName)
+        }
     }
 }
 
 impl std::fmt::Display for GagModeName {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        f.write_str(self.name())
+        f.write_str(&self.0)
     }
 }
 
-/// The number of states a [`GagMode`] markov chain can have.
-pub const GAGMODE_STATES: usize = 16;
+/// A way to rewrite messages.
+///
+/// Gag mode files with a `words` field are [`Self::Dictionary`] and all others are [`Self::Markov`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum GagMode {
+    /// Muffles each letter with a markov chain.
+    Markov(MarkovGagMode),
+    /// Replaces whole words.
+    Dictionary(DictionaryGagMode)
+}
+
+impl<'de> Deserialize<'de> for GagMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        match value.get("words") {
+            Some(_) => serde_json::from_value(value).map(Self::Dictionary),
+            None    => serde_json::from_value(value).map(Self::Markov)
+        }.map_err(serde::de::Error::custom)
+    }
+}
 
-/// A markov chain-based gag mode to rewrite messages.
-pub struct GagMode {
-    /// The character to output for each state.
-    pub chars: [char; GAGMODE_STATES],
+/// A markov chain-based gag mode that muffles each letter.
+///
+/// Can have any number of states, each of which can output any amount of text.
+///
+/// Deserializing checks [`Self::first`] and every row of [`Self::next`] have one weight per state.
+///
+/// Each state is either open (vowel-like) or closed (consonant-like), which [`RewriteOptions::word_shape`] uses.
+///
+/// Letters of a [`Script`] in [`Self::scripts`] are muffled with that chain instead.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawMarkovGagMode", into = "RawMarkovGagMode")]
+pub struct MarkovGagMode {
+    /// The text to output for each state. Usually one character.
+    pub chars: Vec<String>,
     /// The weights of each state to start each word with.
-    pub first: [u8; GAGMODE_STATES],
+    pub first: Vec<u8>,
     /// The weights of each next state.
     ///
     /// `weight_for_next_state = x[current_state][possible_next_state]`.
-    pub next: [[u8; GAGMODE_STATES]; GAGMODE_STATES]
+    pub next: Vec<Vec<u8>>,
+    /// If [`Some`], the states that are open.
+    ///
+    /// If [`None`], states whose text starts with a vowel are open.
+    pub open: Option<Vec<usize>>,
+    /// The chains to muffle letters of other [`Script`]s with, like kana muffles for Japanese.
+    ///
+    /// Letters of scripts not in here use this chain.
+    pub scripts: HashMap<Script, MarkovGagMode>
+}
+
+/// The unchecked form of a [`MarkovGagMode`], as written in gag mode files.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawMarkovGagMode {
+    /// [`MarkovGagMode::chars`].
+    pub chars: Vec<String>,
+    /// [`MarkovGagMode::first`].
+    pub first: Vec<u8>,
+    /// [`MarkovGagMode::next`].
+    pub next: Vec<Vec<u8>>,
+    /// [`MarkovGagMode::open`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open: Option<Vec<usize>>,
+    /// [`MarkovGagMode::scripts`].
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub scripts: HashMap<Script, MarkovGagMode>
+}
+
+/// The enum of errors that can happen when turning a [`RawMarkovGagMode`] into a [`MarkovGagMode`].
+#[derive(Debug, Error)]
+pub enum GagModeShapeError {
+    /// Had no states.
+    #[error("Had no states.")]
+    NoStates,
+    /// [`RawMarkovGagMode::first`] wasn't the same length as [`RawMarkovGagMode::chars`].
+    #[error("Had {chars} chars but {first} first weights.")]
+    FirstLengthMismatch {
+        /// The length of [`RawMarkovGagMode::chars`].
+        chars: usize,
+        /// The length of [`RawMarkovGagMode::first`].
+        first: usize
+    },
+    /// [`RawMarkovGagMode::next`] wasn't the same length as [`RawMarkovGagMode::chars`].
+    #[error("Had {chars} chars but {next} next rows.")]
+    NextLengthMismatch {
+        /// The length of [`RawMarkovGagMode::chars`].
+        chars: usize,
+        /// The length of [`RawMarkovGagMode::next`].
+        next: usize
+    },
+    /// A row of [`RawMarkovGagMode::next`] wasn't the same length as [`RawMarkovGagMode::chars`].
+    #[error("Had {chars} chars but next row {row} had {len} weights.")]
+    NextRowLengthMismatch {
+        /// The length of [`RawMarkovGagMode::chars`].
+        chars: usize,
+        /// The index of the row.
+        row: usize,
+        /// The length of the row.
+        len: usize
+    },
+    /// [`RawMarkovGagMode::open`] had a state that doesn't exist.
+    #[error("Had {chars} chars but state {state} was marked open.")]
+    OpenOutOfRange {
+        /// The length of [`RawMarkovGagMode::chars`].
+        chars: usize,
+        /// The state that doesn't exist.
+        state: usize
+    },
+    /// A chain in [`RawMarkovGagMode::scripts`] had its own [`RawMarkovGagMode::scripts`].
+    #[error("The {0:?} chain had its own per-script chains.")]
+    NestedScripts(Script)
+}
+
+impl TryFrom<RawMarkovGagMode> for MarkovGagMode {
+    type Error = GagModeShapeError;
+
+    fn try_from(value: RawMarkovGagMode) -> Result<Self, Self::Error> {
+        let states = value.chars.len();
+        if states == 0 {Err(GagModeShapeError::NoStates)?}
+        if value.first.len() != states {Err(GagModeShapeError::FirstLengthMismatch {chars: states, first: value.first.len()})?}
+        if value.next .len() != states {Err(GagModeShapeError::NextLengthMismatch  {chars: states, next : value.next .len()})?}
+        for (row, weights) in value.next.iter().enumerate() {
+            if weights.len() != states {Err(GagModeShapeError::NextRowLengthMismatch {chars: states, row, len: weights.len()})?}
+        }
+        for state in value.open.iter().flatten() {
+            if *state >= states {Err(GagModeShapeError::OpenOutOfRange {chars: states, state: *state})?}
+        }
+        for (script, chain) in &value.scripts {
+            if !chain.scripts.is_empty() {Err(GagModeShapeError::NestedScripts(*script))?}
+        }
+
+        Ok(Self {
+            chars: value.chars,
+            first: value.first,
+            next   : value.next,
+            open   : value.open,
+            scripts: value.scripts
+        })
+    }
+}
+
+impl From<MarkovGagMode> for RawMarkovGagMode {
+    fn from(value: MarkovGagMode) -> Self {
+        Self {
+            chars: value.chars,
+            first: value.first,
+            next   : value.next,
+            open   : value.open,
+            scripts: value.scripts
+        }
+    }
+}
+
+/// A problem with a [`GagMode`] found by [`GagMode::problems`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum GagModeProblem {
+    /// [`MarkovGagMode::first`] is all zeros, so no word can be started.
+    #[error("No state has a nonzero first weight.")]
+    NoFirstState,
+    /// A reachable state's row of [`MarkovGagMode::next`] is all zeros, so a word reaching it can't continue.
+    #[error("State {0} ({1:?}) is reachable but has no nonzero next weights.")]
+    DeadEnd(usize, String),
+    /// A state can never be reached.
+    #[error("State {0} ({1:?}) can never be reached.")]
+    Unreachable(usize, String),
+    /// A state outputs nothing or only whitespace.
+    #[error("State {0} ({1:?}) is blank.")]
+    BlankChars(usize, String),
+    /// [`DictionaryGagMode::otherwise`] isn't a registered [`GagMode::Markov`], so words not in the dictionary slip through.
+    #[error("Falls back to {0}, which isn't a registered markov gag mode.")]
+    BadOtherwise(GagModeName),
+    /// A word in [`DictionaryGagMode::words`] has no replacements, so it's treated as not being in the dictionary.
+    #[error("Word {0:?} has no replacements.")]
+    NoReplacements(String),
+    /// A word in [`DictionaryGagMode::words`] isn't lowercase, so it never matches.
+    #[error("Word {0:?} isn't lowercase.")]
+    NotLowercase(String),
+    /// A problem with a chain in [`MarkovGagMode::scripts`].
+    #[error("In the {0:?} chain: {1}")]
+    InScript(Script, Box<GagModeProblem>)
+}
+
+impl GagModeProblem {
+    /// If [`true`], the [`GagMode`] can fail to rewrite messages or let through words it shouldn't.
+    ///
+    /// Otherwise the problem is just probably a mistake.
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            Self::InScript(_, problem) => problem.is_fatal(),
+            _ => matches!(self, Self::NoFirstState | Self::DeadEnd(..) | Self::BadOtherwise(_))
+        }
+    }
 }
 
 /// The enum of errors [`GagMode::rewrite`] can return.
@@ -113,222 +286,383 @@ pub enum GagModeError {
 }
 
 impl GagMode {
-    /// Rewrite a message.
-    pub fn rewrite(&self, text: &str) -> Result<String, GagModeError> {
+    /// Returns every [`GagModeProblem`].
+    ///
+    /// `registry` is used to check the [`GagModeName`]s the mode refers to.
+    ///
+    /// If none of the problems are [fatal](GagModeProblem::is_fatal), [`Self::rewrite`] never fails.
+    pub fn problems(&self, registry: &GagModeRegistry) -> Vec<GagModeProblem> {
+        match self {
+            Self::Markov(mode) => mode.problems(),
+            Self::Dictionary(mode) => mode.problems(registry)
+        }
+    }
+
+    /// Rewrite a message using [`rand::rng`].
+    /// # Errors
+    /// If the call to [`Self::rewrite_with_rng`] returns an error, that error is returned.
+    pub fn rewrite(&self, text: &str, options: RewriteOptions<'_>) -> Result<String, GagModeError> {
+        self.rewrite_with_rng(text, options, &mut rand::rng())
+    }
+
+    /// Rewrite a message using the specified [`Rng`].
+    ///
+    /// Applies the [`FilterStage::Pre`] [`RewriteOptions::filters`], muffles the text, then applies the [`FilterStage::Post`] filters.
+    ///
+    /// Words in [`RewriteOptions::allowed_words`] skip the [`FilterStage::Pre`] filters and muffling.
+    ///
+    /// Each word has a chance to slip through unmuffled depending on [`RewriteOptions::intensity`].
+    ///
+    /// The same [`Rng`] state, text, [`RewriteOptions`], and [`GagMode`] always give the same output.
+    /// # Errors
+    /// If [`MarkovGagMode::first`] or any reachable row of [`MarkovGagMode::next`] is all zeros, returns the error [`GagModeError::WeightError`].
+    pub fn rewrite_with_rng<R: Rng + ?Sized>(&self, text: &str, options: RewriteOptions<'_>, rng: &mut R) -> Result<String, GagModeError> {
         let mut ret = String::with_capacity(text.len());
-        let mut rng = rand::rng();
-        let get_first = WeightedIndex::new(self.first)?;
-        let mut ochar = get_first.sample(&mut rng);
+        let mut last = 0;
+        for found in WORD.find_iter(text).filter(|found| options.allowed_words.contains(&found.as_str().to_lowercase())) {
+            ret.push_str(&self.muffle_with_rng(&text[last..found.start()], options, rng)?);
+            ret.push_str(found.as_str());
+            last = found.end();
+        }
+        ret.push_str(&self.muffle_with_rng(&text[last..], options, rng)?);
+        for filter in options.filters.iter().filter(|filter| filter.stage() == FilterStage::Post) {ret = filter.apply_with_rng(&ret, rng);}
+        Ok(ret)
+    }
+
+    /// Applies the [`FilterStage::Pre`] [`RewriteOptions::filters`] and muffles the text.
+    /// # Errors
+    /// If [`MarkovGagMode::first`] or any reachable row of [`MarkovGagMode::next`] is all zeros, returns the error [`GagModeError::WeightError`].
+    fn muffle_with_rng<R: Rng + ?Sized>(&self, text: &str, options: RewriteOptions<'_>, rng: &mut R) -> Result<String, GagModeError> {
+        if text.is_empty() {return Ok(String::new());}
+        let mut ret = text.to_string();
+        for filter in options.filters.iter().filter(|filter| filter.stage() == FilterStage::Pre) {ret = filter.apply_with_rng(&ret, rng);}
+        match self {
+            Self::Markov    (mode) => mode.muffle_with_rng(&ret, options.intensity, options.word_shape, rng),
+            Self::Dictionary(mode) => mode.muffle_with_rng(&ret, options.intensity, options.word_shape, rng)
+        }
+    }
+}
+
+impl MarkovGagMode {
+    /// Walks the states reachable from [`Self::first`] and returns every [`GagModeProblem`].
+    ///
+    /// If none of the problems are [fatal](GagModeProblem::is_fatal), [`Self::muffle_with_rng`] never fails.
+    pub fn problems(&self) -> Vec<GagModeProblem> {
+        let mut ret = Vec::new();
+
+        let mut reachable = vec![false; self.chars.len()];
+        let mut queue = Vec::new();
+        for (state, weight) in self.first.iter().enumerate() {
+            if *weight != 0 {reachable[state] = true; queue.push(state);}
+        }
+        if queue.is_empty() {ret.push(GagModeProblem::NoFirstState);}
+        while let Some(state) = queue.pop() {
+            for (next, weight) in self.next[state].iter().enumerate() {
+                if *weight != 0 && !reachable[next] {reachable[next] = true; queue.push(next);}
+            }
+        }
+
+        for (state, chars) in self.chars.iter().enumerate() {
+            if reachable[state] {
+                if self.next[state].iter().all(|weight| *weight == 0) {ret.push(GagModeProblem::DeadEnd(state, chars.clone()));}
+            } else {
+                ret.push(GagModeProblem::Unreachable(state, chars.clone()));
+            }
+            if chars.trim().is_empty() {ret.push(GagModeProblem::BlankChars(state, chars.clone()));}
+        }
+
+        let mut scripts = self.scripts.iter().collect::<Vec<_>>();
+        scripts.sort_by_key(|(script, _)| **script);
+        for (script, chain) in scripts {
+            ret.extend(chain.problems().into_iter().map(|problem| GagModeProblem::InScript(*script, Box::new(problem))));
+        }
+
+        ret
+    }
+
+    /// The chain to muffle letters of a [`Script`] with.
+    pub fn chain_for(&self, script: Script) -> &Self {
+        self.scripts.get(&script).unwrap_or(self)
+    }
+
+    /// If [`true`], `state` is open (vowel-like). Otherwise it's closed (consonant-like).
+    pub fn is_open(&self, state: usize) -> bool {
+        match &self.open {
+            Some(open) => open.contains(&state),
+            None => self.chars[state].chars().next().is_some_and(is_vowel)
+        }
+    }
+
+    /// Muffle every letter of `text`, letting some words slip through depending on `intensity`.
+    ///
+    /// If `word_shape` is [`true`], vowels and consonants are muffled with open and closed states.
+    /// # Errors
+    /// If [`Self::first`] or any reachable row of [`Self::next`] is all zeros, returns the error [`GagModeError::WeightError`].
+    pub fn muffle_with_rng<R: Rng + ?Sized>(&self, text: &str, intensity: Intensity, word_shape: bool, rng: &mut R) -> Result<String, GagModeError> {
+        match word_shape {
+            false => self.muffle_plain_with_rng (text, intensity, rng),
+            true  => self.muffle_shaped_with_rng(text, intensity, rng)
+        }
+    }
+
+    /// [`Self::muffle_with_rng`] for when `word_shape` is [`false`].
+    /// # Errors
+    /// If [`Self::first`] or any reachable row of [`Self::next`] is all zeros, returns the error [`GagModeError::WeightError`].
+    fn muffle_plain_with_rng<R: Rng + ?Sized>(&self, text: &str, intensity: Intensity, rng: &mut R) -> Result<String, GagModeError> {
+        let mut ret = String::with_capacity(text.len());
+        let mut script = Script::Latin;
+        let mut chain = self;
+        let mut get_first = WeightedIndex::new(&chain.first)?;
+        let mut ochar = get_first.sample(rng);
+        let mut leaking = None;
+        for c in text.chars() {
+            if c.is_alphabetic() {
+                if Script::of(c) != script {
+                    script = Script::of(c);
+                    chain = self.chain_for(script);
+                    get_first = WeightedIndex::new(&chain.first)?;
+                    ochar = get_first.sample(rng);
+                    leaking = None;
+                }
+                if *leaking.get_or_insert_with(|| intensity.leaks(rng)) {
+                    ret.push(c);
+                } else {
+                    ret.push_str(&cased_like(&chain.chars[ochar], c));
+                    ochar = WeightedIndex::new(&chain.next[ochar])?.sample(rng);
+                }
+                if script.is_per_character() {
+                    ochar = get_first.sample(rng);
+                    leaking = None;
+                }
+            } else {
+                ret.push(c);
+                ochar = get_first.sample(rng);
+                leaking = None;
+            }
+        }
+        Ok(ret)
+    }
+
+    /// [`Self::muffle_with_rng`] for when `word_shape` is [`true`].
+    ///
+    /// Each vowel becomes an open state and each consonant becomes a closed state, so words keep their length and syllables.
+    ///
+    /// If the current state has no next states of the right kind, any next state is used.
+    /// # Errors
+    /// If [`Self::first`] or any reachable row of [`Self::next`] is all zeros, returns the error [`GagModeError::WeightError`].
+    fn muffle_shaped_with_rng<R: Rng + ?Sized>(&self, text: &str, intensity: Intensity, rng: &mut R) -> Result<String, GagModeError> {
+        let mut ret = String::with_capacity(text.len());
+        let mut script = Script::Latin;
+        let mut chain = self;
+        let mut state = None;
+        let mut leaking = None;
         for c in text.chars() {
             if c.is_alphabetic() {
-                ret.push_str(&if c.is_uppercase() {self.chars[ochar].to_uppercase().to_string()} else {self.chars[ochar].to_lowercase().to_string()});
-                ochar = WeightedIndex::new(self.next[ochar])?.sample(&mut rng);
+                if Script::of(c) != script {
+                    script = Script::of(c);
+                    chain = self.chain_for(script);
+                    state = None;
+                    leaking = None;
+                }
+                if *leaking.get_or_insert_with(|| intensity.leaks(rng)) {
+                    ret.push(c);
+                } else {
+                    let weights = match state {
+                        Some(state) => &chain.next[state],
+                        None => &chain.first
+                    };
+                    let open = is_vowel(c);
+                    let next = match WeightedIndex::new(weights.iter().enumerate().map(|(state, weight)| if chain.is_open(state) == open {*weight} else {0})) {
+                        Ok(shaped) => shaped.sample(rng),
+                        Err(_) => WeightedIndex::new(weights)?.sample(rng)
+                    };
+                    ret.push_str(&cased_like(&chain.chars[next], c));
+                    state = Some(next);
+                }
+                if script.is_per_character() {
+                    state = None;
+                    leaking = None;
+                }
             } else {
                 ret.push(c);
-                ochar = get_first.sample(&mut rng);
+                state = None;
+                leaking = None;
             }
         }
         Ok(ret)
     }
 }
 
-/// The empty [`GagMode`]. Used for copy-pasting.
-#[allow(dead_code, reason = "Used for copy pasting")]
-pub const EMPTY_GAGMODE: GagMode = GagMode {
-    chars: [' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' '],
-    first: [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-    next: [
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ]
-    ]
-};
-
-/// [`GagModeName::Gag`].
-pub const GAG_GAGMODE: GagMode = GagMode {
-    chars: ['h','m','f' , ' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' '],
-    first: [ 4 , 2 , 1  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-    next: [
-           [ 2 , 4 , 1  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 1 , 4 , 2  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 2 , 1 , 4  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ]
-    ]
-};
-
-/// [`GagModeName::Dog`]
-pub const DOG_GAGMODE: GagMode = GagMode {
-    chars: ['a','w','r','u','f' , 'w','o','f' , 'b','a','r','k','!' , 'a','w','o'],
-    first: [ 4 , 2,  2 , 0 , 0  ,  4 , 0 , 0  ,  1 , 0 , 0 , 0 , 0  ,  1 , 0 , 0 ],
-    next: [
-           [ 1 , 2 , 2 , 1 , 0  ,  0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 ],
-           [ 1 , 1 , 2 , 2 , 0  ,  0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 ],
-           [ 1 , 1 , 2 , 4 , 4  ,  0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 ],
-           [ 1 , 1 , 1 , 1 , 4  ,  0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 ],
-           [ 1 , 1 , 2 , 1 , 2  ,  0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 ],
-
-           [ 0 , 0 , 0 , 0 , 0  ,  1 , 4 , 0  ,  0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0  ,  1 , 4 , 2  ,  0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 1  ,  0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 ],
-
-           [ 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0  ,  0 , 4 , 0 , 0 , 0  ,  0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0  ,  0 , 1 , 2 , 0 , 0  ,  0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0  ,  0 , 0 , 1 , 1 , 0  ,  0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0  ,  0 , 0 , 1 , 1 , 1  ,  0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0  ,  0 , 0 , 0 , 0 , 1  ,  0 , 0 , 0 ],
-
-           [ 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0  ,  1 , 1 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0  ,  0 , 1 , 2 ],
-           [ 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0  ,  1 , 1 , 4 ]
-    ]
-};
-
-/// [`GagModeName::Cow`]
-pub const COW_GAGMODE: GagMode = GagMode {
-    chars: ['m','o' , ' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' '],
-    first: [ 1 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-    next: [
-           [ 1 , 1  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 1  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-
-           [ 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ]
-    ]
-};
-
-/// [`GagModename::Fox`]
-pub const FOX_GAGMODE: GagMode = GagMode {
-    chars: ['a','e','h' , ' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' '],
-    first: [ 1 , 1 , 1  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-    next: [
-           [ 1 , 1 , 1  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 1 , 1 , 1  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 1 , 1 , 1  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ]
-    ]
-};
-
-/// [`GagModeName::Cat`]
-pub const CAT_GAGMODE: GagMode = GagMode {
-    chars: ['m','r','e','o','a','u','w' , ' ',' ',' ',' ',' ',' ',' ',' ',' '],
-    first: [ 4 , 2 , 1 , 0 , 1 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-    next: [
-           [ 1 , 2 , 2 , 1 , 2 , 1 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 1 , 2 , 2 , 2 , 1 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 2 , 2 , 2 , 2 , 1  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 2 , 2 , 2 , 2 , 1  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 2 , 2 , 2 , 2 , 1  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 2 , 2 , 2 , 2 , 1  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 1 , 1 , 1 , 1 , 2  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ]
-    ]
-};
-
-/// [`GagModeName::Seal`]
-pub const SEAL_GAGMODE: GagMode = GagMode {
-    chars: ['g','i','h','p','b','e','a','f' , ' ',' ',' ',' ',' ',' ',' ',' '],
-    first: [ 1 , 1 , 1 , 1 , 1 , 1 , 1 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-    next: [
-           [ 1 , 1 , 1 , 0 , 0 , 4 , 2 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 1 , 2 , 2 , 1 , 2 , 1 , 1 , 1  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 1 , 1 , 1 , 2 , 1 , 0 , 0 , 2  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 1 , 4 , 0 , 1 , 0 , 0 , 2  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 1 , 1 , 0 , 1 , 1 , 0 , 2  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 2 , 1 , 1 , 0 , 0 , 0 , 2 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 1 , 1 , 1 , 1 , 1 , 1 , 1 , 1  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 1 , 2 , 2 , 1 , 1 , 1 , 0 , 4  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0  ,  0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ]
-    ]
-};
-
-/// [`GagModeName::Bee`].
-pub const BEE_GAGMODE: GagMode = GagMode {
-    chars: ['b','u','z',' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' ',' '],
-    first: [ 4 , 0 , 1 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-    next: [
-           [ 0 , 1 , 1 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 1 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 1 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ],
-           [ 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 , 0 ]
-    ]
-};
+/// A word, including apostrophes between letters like in `don't`.
+///
+/// Each Chinese character and kana is its own word, as those scripts don't put spaces between words.
+pub static WORD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[\p{Han}\p{Hiragana}\p{Katakana}]|[\p{Alphabetic}--\p{Han}--\p{Hiragana}--\p{Katakana}]+(?:'[\p{Alphabetic}--\p{Han}--\p{Hiragana}--\p{Katakana}]+)*").expect("The WORD regex to be valid"));
+
+/// Options for [`GagMode::rewrite`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RewriteOptions<'a> {
+    /// How muffled messages are.
+    pub intensity: Intensity,
+    /// If [`true`], muffle vowels and consonants with open and closed states so words keep their shape.
+    pub word_shape: bool,
+    /// The [`GagFilter`]s to apply, in order.
+    pub filters: &'a [GagFilter],
+    /// Lowercase words that are never muffled.
+    pub allowed_words: &'a BTreeSet<String>
+}
+
+/// The empty set [`RewriteOptions::default`] uses for [`RewriteOptions::allowed_words`].
+static NO_ALLOWED_WORDS: BTreeSet<String> = BTreeSet::new();
+
+impl Default for RewriteOptions<'_> {
+    fn default() -> Self {
+        Self {
+            intensity    : Default::default(),
+            word_shape   : false,
+            filters      : &[],
+            allowed_words: &NO_ALLOWED_WORDS
+        }
+    }
+}
+
+/// The vowels [`is_vowel`] checks for, including `y`.
+const VOWELS: &str = "aeiouyàáâãäåæèéêëìíîïòóôõöøœùúûüýÿαεηιουωάέήίόύώаеёиоуыэюяіїє";
+
+/// If [`true`], `c` is a vowel.
+fn is_vowel(c: char) -> bool {
+    c.to_lowercase().any(|c| VOWELS.contains(c))
+}
+
+/// Uppercases `text` if `like` is uppercase and lowercases it if `like` is lowercase.
+///
+/// If `like` is from a script without case, `text` is kept as is.
+fn cased_like(text: &str, like: char) -> String {
+    if like.is_uppercase() {
+        text.to_uppercase()
+    } else if like.is_lowercase() {
+        text.to_lowercase()
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand_chacha::ChaCha8Rng;
+
+    /// The text [`golden_outputs`] rewrites.
+    const GOLDEN_TEXT: &str = "Hello, World! This is a TEST of the gag.";
+
+    /// The output of every built-in gag mode for [`GOLDEN_TEXT`] with a [`ChaCha8Rng`] seeded with 1.
+    const GOLDEN_OUTPUTS: [(&str, &str); 11] = [
+        ("Gag"   , "Hmfhh, Hmmff! Mmhf mh f MHFH hm hff mfh."),
+        ("Sock"  , "Hmfhh, Hmmff! Mmhf mh f MHFH hm hff mfh."),
+        ("Dog"   , "Rufaw, Arrfu! Wwwo ww a WWOO ru auf woo."),
+        ("Puppy" , "Rufaw, Arrfu! Wwwo ww a WWOO ru auf woo."),
+        ("Cow"   , "Mmooo, Moooo! Mmmo mm m MMOO mm moo moo."),
+        ("Fox"   , "Eehaa, Ahahh! Haah ha h HAHA ee ahh hha."),
+        ("Cat"   , "Rowee, Maoww! Reeu rr a EEWO me muu ewo."),
+        ("Seal"  , "Phfgi, Iahff! Biga bi a EGAH ph gae eah."),
+        ("Bee"   , "Buzzz, Bzzzz! Buzz bu z ZZZZ bu bzz bzz."),
+        ("Pirate", "Ahoy, Seven seas! Fhhm be m FFFF o' th' fhm."),
+        ("Muzzle", "Hmfhh, Hhmmf! Hmmm fh h FFFH hm hhh mff.")
+    ];
+
+    #[test]
+    fn golden_outputs() {
+        let registry = GagModeRegistry::builtin();
+        for (name, _) in BUILTIN_GAG_MODES {
+            let (_, expected) = GOLDEN_OUTPUTS.iter().find(|(golden_name, _)| *golden_name == name).unwrap_or_else(|| panic!("{name} to have a golden output"));
+            let output = registry.definition(&GagModeName(name.to_string())).expect("The gag mode to be built-in")
+                .mode.rewrite_with_rng(GOLDEN_TEXT, RewriteOptions::default(), &mut ChaCha8Rng::seed_from_u64(1)).expect("The built-in gag modes to be valid");
+            assert_eq!(output, *expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn builtins_have_no_problems() {
+        assert_eq!(GagModeRegistry::builtin().problems(), Vec::new());
+    }
+
+    #[test]
+    fn same_seed_same_output() {
+        let registry = GagModeRegistry::builtin();
+        let mode = &registry.definition(&GagModeName::default()).expect("The default gag mode to be built-in").mode;
+        let text = "The quick brown fox jumps over the lazy dog.";
+        assert_eq!(
+            mode.rewrite_with_rng(text, RewriteOptions::default(), &mut ChaCha8Rng::seed_from_u64(1234)).expect("The built-in gag modes to be valid"),
+            mode.rewrite_with_rng(text, RewriteOptions::default(), &mut ChaCha8Rng::seed_from_u64(1234)).expect("The built-in gag modes to be valid")
+        );
+    }
+
+    #[test]
+    fn intensity() {
+        let registry = GagModeRegistry::builtin();
+        let mode = &registry.definition(&GagModeName::default()).expect("The default gag mode to be built-in").mode;
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let mut rng = ChaCha8Rng::seed_from_u64(1234);
+        assert_eq!(mode.rewrite_with_rng(text, RewriteOptions {intensity: Intensity::new(0), ..Default::default()}, &mut rng).expect("The built-in gag modes to be valid"), text);
+        let half = mode.rewrite_with_rng(text, RewriteOptions {intensity: Intensity::new(50), ..Default::default()}, &mut rng).expect("The built-in gag modes to be valid");
+        assert!(half.split(' ').zip(text.split(' ')).any(|(gagged, original)| gagged == original));
+        assert!(half.split(' ').zip(text.split(' ')).any(|(gagged, original)| gagged != original));
+    }
+
+    #[test]
+    fn word_shape() {
+        let registry = GagModeRegistry::builtin();
+        let text = "HELP me, strawberry banana";
+        for (name, _) in BUILTIN_GAG_MODES {
+            let mode = &registry.definition(&GagModeName(name.to_string())).expect("The gag mode to be built-in").mode;
+            if !matches!(mode, GagMode::Markov(_)) {continue;}
+            let output = mode.rewrite_with_rng(text, RewriteOptions {word_shape: true, ..Default::default()}, &mut ChaCha8Rng::seed_from_u64(1)).expect("The built-in gag modes to be valid");
+            assert_eq!(output.chars().count(), text.chars().count(), "{name}");
+            if name == "Cat" {
+                for (c, state) in text.chars().zip(output.chars()).filter(|(c, _)| c.is_alphabetic()) {
+                    assert_eq!(is_vowel(c), is_vowel(state), "{name}: {output}");
+                    assert_eq!(c.is_uppercase(), state.is_uppercase(), "{name}: {output}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dictionary() {
+        let registry = GagModeRegistry::builtin();
+        let mode = &registry.definition(&GagModeName("Muzzle".to_string())).expect("The gag mode to be built-in").mode;
+        let output = mode.rewrite_with_rng("PLEASE let me go, I said please! Red", RewriteOptions::default(), &mut ChaCha8Rng::seed_from_u64(1)).expect("The built-in gag modes to be valid");
+        assert!(output.starts_with("PLEASE ") && output.ends_with(" please! Red"), "{output}");
+        assert!(!output.contains("let") && !output.contains("said"), "{output}");
+    }
+
+    #[test]
+    fn allowed_words() {
+        let registry = GagModeRegistry::builtin();
+        let mode = &registry.definition(&GagModeName::default()).expect("The default gag mode to be built-in").mode;
+        let allowed_words = ["please".to_string(), "don't".to_string()].into();
+        let output = mode.rewrite_with_rng("Please, PLEASE don't stop", RewriteOptions {allowed_words: &allowed_words, ..Default::default()}, &mut ChaCha8Rng::seed_from_u64(1)).expect("The built-in gag modes to be valid");
+        assert!(output.starts_with("Please, PLEASE don't "), "{output}");
+        assert!(!output.ends_with("stop"), "{output}");
+    }
+
+    #[test]
+    fn scripts() {
+        let registry = GagModeRegistry::builtin();
+        let mode = &registry.definition(&GagModeName::default()).expect("The default gag mode to be built-in").mode;
+        let rewrite = |text| mode.rewrite_with_rng(text, RewriteOptions::default(), &mut ChaCha8Rng::seed_from_u64(1)).expect("The built-in gag modes to be valid");
+
+        let output = rewrite("こんにちは、世界！ Hi");
+        assert_eq!(output.chars().count(), "こんにちは、世界！ Hi".chars().count(), "{output}");
+        assert!(output.chars().take(5).all(|c| "ふんむ".contains(c)), "{output}");
+        assert!(output.chars().skip(6).take(2).all(|c| "ふんむ".contains(c)), "{output}");
+        assert!(output.ends_with(['h', 'm', 'f']), "{output}");
+
+        let output = rewrite("Привет МИР");
+        assert!(output.chars().all(|c| "хмфХМФ ".contains(c)), "{output}");
+        assert!(output.starts_with(['Х', 'М', 'Ф']), "{output}");
+        assert!(output.chars().rev().take(3).all(char::is_uppercase), "{output}");
+
+        assert_eq!(WORD.find_iter("日本語 don't").map(|found| found.as_str()).collect::<Vec<_>>(), ["日", "本", "語", "don't"]);
+    }
+}
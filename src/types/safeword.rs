@@ -5,6 +5,8 @@ use std::collections::HashSet;
 use serde::{Serialize, Deserialize};
 use serenity::model::id::{GuildId, ChannelId};
 
+use crate::types::*;
+
 /// Configuration for where to ignore [`Gag`]s.
 #[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Safewords {
@@ -58,19 +60,26 @@ impl Safewords {
 
     /// Get the relevant safewords.
     ///
+    /// In threads, a safeword on the thread's channel counts as [`SafewordLocation::Channel`].
+    ///
     /// Used by the `/status` command.
-    pub fn get_relevant_safewords(&self, channel: ChannelId, server: Option<GuildId>) -> Vec<SafewordLocation> {
+    pub fn get_relevant_safewords(&self, location: GagLocation) -> Vec<SafewordLocation> {
         let mut ret = Vec::new();
         if self.global {ret.push(SafewordLocation::Global);}
-        if server.is_some_and(|server| self.servers.contains(&server)) {ret.push(SafewordLocation::Server);}
-        if self.channels.contains(&channel) {ret.push(SafewordLocation::Channel);}
+        if location.server.is_some_and(|server| self.servers.contains(&server)) {ret.push(SafewordLocation::Server);}
+        if self.is_safewording_channel(location) {ret.push(SafewordLocation::Channel);}
         ret
     }
 
     /// Checks if a channel is being safeworded.
     ///
     /// Let's see you include the server in that description with the proper hypothetical branch handling.
-    pub fn is_safewording(&self, channel: ChannelId, server: Option<GuildId>) -> bool {
-        self.global || server.is_some_and(|server| self.servers.contains(&server)) || self.channels.contains(&channel)
+    pub fn is_safewording(&self, location: GagLocation) -> bool {
+        self.global || location.server.is_some_and(|server| self.servers.contains(&server)) || self.is_safewording_channel(location)
+    }
+
+    /// Checks if the channel of `location`, or the channel a thread is in, is being safeworded.
+    fn is_safewording_channel(&self, location: GagLocation) -> bool {
+        self.channels.contains(&location.channel) || location.parent.is_some_and(|parent| self.channels.contains(&parent))
     }
 }
@@ -0,0 +1,44 @@
+//! Writing systems, so each can be muffled its own way.
+
+use serde::{Serialize, Deserialize};
+
+/// The writing system a letter belongs to.
+///
+/// Used to pick which chain of a [`MarkovGagMode`] muffles a letter and where words start and end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Script {
+    /// Latin letters, including accented ones.
+    Latin,
+    /// Greek letters.
+    Greek,
+    /// Cyrillic letters.
+    Cyrillic,
+    /// Korean Hangul.
+    Hangul,
+    /// Japanese hiragana and katakana.
+    Kana,
+    /// Chinese characters, including Japanese kanji.
+    Han,
+    /// Every other script.
+    Other
+}
+
+impl Script {
+    /// Gets the [`Script`] of a letter.
+    pub fn of(c: char) -> Self {
+        match c {
+            '\u{0000}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}' | '\u{FF21}'..='\u{FF5A}' => Self::Latin,
+            '\u{0370}'..='\u{03FF}' | '\u{1F00}'..='\u{1FFF}' => Self::Greek,
+            '\u{0400}'..='\u{052F}' | '\u{1C80}'..='\u{1C8F}' | '\u{2DE0}'..='\u{2DFF}' | '\u{A640}'..='\u{A69F}' => Self::Cyrillic,
+            '\u{1100}'..='\u{11FF}' | '\u{3130}'..='\u{318F}' | '\u{A960}'..='\u{A97F}' | '\u{AC00}'..='\u{D7FF}' => Self::Hangul,
+            '\u{3040}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}' | '\u{FF66}'..='\u{FF9F}' => Self::Kana,
+            '\u{3005}' | '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{F900}'..='\u{FAFF}' | '\u{20000}'..='\u{323AF}' => Self::Han,
+            _ => Self::Other
+        }
+    }
+
+    /// If [`true`], the script doesn't put spaces between words, so each letter is treated as its own word.
+    pub fn is_per_character(self) -> bool {
+        matches!(self, Self::Kana | Self::Han)
+    }
+}
@@ -1,33 +1,107 @@
 //! The overall bot state.
 
-use std::sync::RwLock;
-use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::collections::{HashMap, HashSet};
 use std::collections::hash_map::Entry;
 
 use serde::{Serialize, Deserialize};
+use tokio::sync::Notify;
 use serenity::model::{channel::{Message, MessageReference, MessageReferenceKind}, id::{UserId, ChannelId}, timestamp::Timestamp};
 use thiserror::Error;
 
 use crate::types::*;
+use crate::markup::gagged_word_count;
 
 /// The current state of the bot.
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// Stored as [`RawState`], so state files keep each part of every [`UserState`] in its own map.
+#[derive(Debug, Default, Deserialize)]
+#[serde(from = "RawState")]
 pub struct State {
-    /// The [`GaggeeTrust`] for each user.
+    /// Each user's [`UserState`], each behind its own lock.
+    ///
+    /// The outer lock is only write locked to add and remove users.
+    pub users: RwLock<HashMap<UserId, Arc<RwLock<UserState>>>>,
+    /// The users whose data changed since the last call to [`Self::take_changed_users`].
+    ///
+    /// Used by [`crate::store::StateStore`]s to only save what changed.
+    pub changed_users: Mutex<HashSet<UserId>>,
+    /// Notified whenever [`Self::changed_users`] gets a user, so [`crate::store::persist`] knows to save.
+    pub changed: Notify,
+    /// Notified whenever a gag that can expire is added or has its timer changed, so [`crate::expiry::expire_gags`] knows to recheck [`Self::next_expiry`].
+    pub gags_changed: Notify
+}
+
+/// The format [`State`] is stored in.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RawState {
+    /// [`UserState::trusts`] for each user.
+    #[serde(default)]
+    pub trusts: HashMap<UserId, GaggeeTrust>,
+    /// [`UserState::gags`] for each user.
+    #[serde(default)]
+    pub gags: HashMap<UserId, HashMap<GagScope, Gag>>,
+    /// [`UserState::max_msg_length`] for each user.
+    #[serde(default)]
+    pub max_msg_lengths: HashMap<UserId, usize>,
+    /// [`UserState::preserve_markup`] for each user.
     #[serde(default)]
-    pub trusts: RwLock<HashMap<UserId, GaggeeTrust>>,
-    /// The [`Gag`]s for each user.
+    pub preserve_markup: HashMap<UserId, bool>,
+    /// [`UserState::safewords`] for each user.
     #[serde(default)]
-    pub gags: RwLock<HashMap<UserId, HashMap<ChannelId, Gag>>>,
-    /// The length of a message to gag for each user.
+    pub safewords: HashMap<UserId, Safewords>,
+    /// [`UserState::gag_defaults`] for each user.
     #[serde(default)]
-    pub max_msg_lengths: RwLock<HashMap<UserId, usize>>,
-    /// The [`Safewords`]s for each user.
+    pub gag_defaults: HashMap<UserId, GagDefaults>,
+    /// [`UserState::announce_expiry`] for each user that has it on.
     #[serde(default)]
-    pub safewords: RwLock<HashMap<UserId, Safewords>>,
-    /// Default values for a [`Gag`].
+    pub announce_expiry: HashMap<UserId, bool>,
+    /// [`UserState::thread_opt_outs`] for each user that has any.
     #[serde(default)]
-    pub gag_defaults: RwLock<HashMap<UserId, GagDefaults>>
+    pub thread_opt_outs: HashMap<UserId, HashSet<ChannelId>>
+}
+
+impl From<RawState> for State {
+    fn from(value: RawState) -> Self {
+        let mut users = HashMap::<UserId, UserState>::new();
+        for (user, x) in value.trusts          {users.entry(user).or_default().trusts          = x      ;}
+        for (user, x) in value.gags            {users.entry(user).or_default().gags            = x      ;}
+        for (user, x) in value.max_msg_lengths {users.entry(user).or_default().max_msg_length  = Some(x);}
+        for (user, x) in value.preserve_markup {users.entry(user).or_default().preserve_markup = Some(x);}
+        for (user, x) in value.safewords       {users.entry(user).or_default().safewords       = x      ;}
+        for (user, x) in value.gag_defaults    {users.entry(user).or_default().gag_defaults    = x      ;}
+        for (user, x) in value.announce_expiry {users.entry(user).or_default().announce_expiry = x      ;}
+        for (user, x) in value.thread_opt_outs {users.entry(user).or_default().thread_opt_outs = x      ;}
+        Self {
+            users: RwLock::new(users.into_iter().map(|(user, x)| (user, Arc::new(RwLock::new(x)))).collect()),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&State> for RawState {
+    fn from(value: &State) -> Self {
+        let mut ret = Self::default();
+        for (user, x) in value.users.read().expect("No panics").iter() {
+            let x = x.read().expect("No panics");
+            if x.trusts       != GaggeeTrust::default() {ret.trusts      .insert(*user, x.trusts      .clone());}
+            if !x.gags.is_empty()                       {ret.gags        .insert(*user, x.gags        .clone());}
+            if let Some(max_msg_length ) = x.max_msg_length  {ret.max_msg_lengths.insert(*user, max_msg_length );}
+            if let Some(preserve_markup) = x.preserve_markup {ret.preserve_markup.insert(*user, preserve_markup);}
+            if x.safewords    != Safewords::default()   {ret.safewords   .insert(*user, x.safewords   .clone());}
+            if x.gag_defaults != GagDefaults::default() {ret.gag_defaults.insert(*user, x.gag_defaults.clone());}
+            if x.announce_expiry {ret.announce_expiry.insert(*user, true);}
+            if !x.thread_opt_outs.is_empty() {ret.thread_opt_outs.insert(*user, x.thread_opt_outs.clone());}
+        }
+        ret
+    }
+}
+
+impl Serialize for State {
+    /// Serializes as [`RawState`].
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RawState::from(self).serialize(serializer)
+    }
 }
 
 /// The errors that [`State::gag`] can return.
@@ -42,9 +116,21 @@ pub enum GagError {
     /// Tried to gag someone in a mode they haven't consented to.
     #[error("Tried to gag someone in a mode they haven't consented to.")]
     NoConsentForMode,
+    /// Tried to gag someone with a filter they haven't consented to.
+    #[error("Tried to gag someone with a filter they haven't consented to.")]
+    NoConsentForFilter(GagFilter),
     /// Tried to gag someone who was already gagged.
     #[error("Tried to gag someone who was already gagged.")]
-    AlreadyGagged
+    AlreadyGagged,
+    /// Tried to keep the key of a gag that isn't tied.
+    #[error("Tried to keep the key of a gag that isn't tied.")]
+    KeyWithoutTie,
+    /// Tried to keep the key of your own gag.
+    #[error("Tried to keep the key of your own gag.")]
+    CantKeepOwnKey,
+    /// Tried to keep the key of someone's gag without their consent to untie them.
+    #[error("Tried to keep the key of someone's gag without their consent to untie them.")]
+    NoConsentForKey
 }
 
 /// The errors that [`State::ungag`] can return.
@@ -59,15 +145,53 @@ pub enum UngagError {
     /// Tried to ungag someone from a mode they haven't consented to you ungagging them from.
     #[error("Tried to ungag someone from a mode they haven't consented to you ungagging them from.")]
     NoConsentForMode(GagModeName),
+    /// Tried to ungag someone from a filter they haven't consented to you ungagging them from.
+    #[error("Tried to ungag someone from a filter they haven't consented to you ungagging them from.")]
+    NoConsentForFilter(GagFilter),
     /// Tried to untie yourself.
     #[error("Tried to untie yourself")]
     CantUntieYourself,
+    /// Tried to ungag someone whose tied gag has a key you don't hold.
+    #[error("Tried to ungag someone whose tied gag has a key you don't hold.")]
+    NotKeyholder,
     /// Tried to ungag someone who wasn't gagged.
     #[error("Tried to ungag someone who wasn't gagged.")]
     WasntGagged
 }
 
+/// The errors [`State::change_timer`] can return.
+#[derive(Debug, Error)]
+pub enum ChangeTimerError {
+    /// Tried to add time to someone's gag without their consent.
+    #[error("Tried to add time to someone's gag without their consent.")]
+    NoConsentForExtend,
+    /// Tried to remove time from someone's gag without their consent.
+    #[error("Tried to remove time from someone's gag without their consent.")]
+    NoConsentForShorten,
+    /// Tried to change the timer of a gag in a mode they haven't consented to.
+    #[error("Tried to change the timer of a gag in a mode they haven't consented to.")]
+    NoConsentForMode(GagModeName),
+    /// Tried to shorten someone else's tied gag without their consent to untie them.
+    #[error("Tried to shorten someone else's tied gag without their consent to untie them.")]
+    NoConsentForUntie,
+    /// Tried to shorten your own tied gag.
+    #[error("Tried to shorten your own tied gag.")]
+    CantUntieYourself,
+    /// Tried to shorten a tied gag whose key you don't hold.
+    #[error("Tried to shorten a tied gag whose key you don't hold.")]
+    NotKeyholder,
+    /// Tried to change the timer of someone who wasn't gagged.
+    #[error("Tried to change the timer of someone who wasn't gagged.")]
+    WasntGagged,
+    /// Tried to change the timer of a gag that lasts forever.
+    #[error("Tried to change the timer of a gag that lasts forever.")]
+    NoTimer,
+    /// The new end of the gag is too far in the future.
+    #[error("The new end of the gag is too far in the future.")]
+    TooLong
+}
 
+/// The errors [`State::change_gag`] can return.
 #[derive(Debug, Error)]
 pub enum ChangeGagError {
     /// Tried to gag someone without their consent.
@@ -76,6 +200,18 @@ pub enum ChangeGagError {
     /// Tried to gag someone in a mode they haven't consented to.
     #[error("Tried to gag someone in a mode they haven't consented to.")]
     NoConsentForMode(GagModeName),
+    /// Tried to gag someone with a filter they haven't consented to.
+    #[error("Tried to gag someone with a filter they haven't consented to.")]
+    NoConsentForFilter(GagFilter),
+    /// Tried to loosen someone else's tied gag without their consent to untie them.
+    #[error("Tried to loosen someone else's tied gag without their consent to untie them.")]
+    NoConsentForUntie,
+    /// Tried to loosen your own tied gag.
+    #[error("Tried to loosen your own tied gag.")]
+    CantUntieYourself,
+    /// Tried to loosen a tied gag whose key you don't hold.
+    #[error("Tried to loosen a tied gag whose key you don't hold.")]
+    NotKeyholder,
     /// Tried to ungag someone who wasn't gagged.
     #[error("Tried to ungag someone who wasn't gagged.")]
     WasntGagged
@@ -95,7 +231,13 @@ pub enum TieError {
     NoConsentForTie,
     /// Tried to tie someone who doesn't consent to you tying them in their current gag's mode.
     #[error("Tried to tie someone who doesn't consent to you tying them in their current gag's mode.")]
-    NoConsentForMode(GagModeName)
+    NoConsentForMode(GagModeName),
+    /// Tried to keep the key of your own gag.
+    #[error("Tried to keep the key of your own gag.")]
+    CantKeepOwnKey,
+    /// Tried to keep the key of someone's gag without their consent to untie them.
+    #[error("Tried to keep the key of someone's gag without their consent to untie them.")]
+    NoConsentForKey
 }
 
 /// The errors [`State::untie`] can return.
@@ -113,198 +255,464 @@ pub enum UntieError {
     /// You can't untie yourself.
     #[error("You can't untie yourself.")]
     CantUntieYourself,
+    /// Tried to untie someone whose gag has a key you don't hold.
+    #[error("Tried to untie someone whose gag has a key you don't hold.")]
+    NotKeyholder,
     /// Tried to untie someone who doesn't consent to you untying them in their current gag's mode.
     #[error("Tried to untie someone who doesn't consent to you untying them in their current gag's mode.")]
     NoConsentForMode(GagModeName)
 }
 
+/// The errors [`State::give_key`] can return.
+#[derive(Debug, Error)]
+pub enum GiveKeyError {
+    /// Tried to give the key of someone who wasn't gagged.
+    #[error("Tried to give the key of someone who wasn't gagged.")]
+    WasntGagged,
+    /// Tried to give the key of a gag that doesn't have one.
+    #[error("Tried to give the key of a gag that doesn't have one.")]
+    NoKey,
+    /// Tried to give away a key you don't hold.
+    #[error("Tried to give away a key you don't hold.")]
+    NotKeyholder,
+    /// Tried to give the key to the gaggee.
+    #[error("Tried to give the key to the gaggee.")]
+    GaggeeCantHoldKey,
+    /// Tried to give the key to someone the gaggee doesn't consent to untying them.
+    #[error("Tried to give the key to someone the gaggee doesn't consent to untying them.")]
+    NoConsentForUntie,
+    /// Tried to give the key to someone the gaggee doesn't consent to untying them in their current gag's mode.
+    #[error("Tried to give the key to someone the gaggee doesn't consent to untying them in their current gag's mode.")]
+    NoConsentForMode(GagModeName)
+}
+
 impl State {
-    /// Does cleanup stuff for both privacy and not giving weird answers about expired gags.
+    /// Removes expired gags, settings that are the same as their defaults, and users with no data.
+    ///
+    /// Each user is read locked one at a time, and only write locked if [`UserState::needs_cleanup`].
+    ///
+    /// Returns the gags that expired, so they can be [announced](crate::expiry::announce).
+    pub fn cleanup(&self, now: Timestamp) -> Vec<ExpiredGag> {
+        let mut changed = HashSet::new();
+        let mut empty = Vec::new();
+        let mut expired = Vec::new();
+        for (user, x) in self.users.read().expect("No panics").iter() {
+            if !x.read().expect("No panics").needs_cleanup(now) {continue;}
+            let mut x = x.write().expect("No panics");
+            let scopes = x.expire_gags(now);
+            if x.cleanup() || !scopes.is_empty() {changed.insert(*user);}
+            expired.extend(scopes.into_iter().map(|scope| ExpiredGag {user: *user, scope, announce: x.announce_expiry}));
+            if x.is_empty() {empty.push(*user);}
+        }
+        if !empty.is_empty() {
+            let mut users = self.users.write().expect("No panics");
+            for user in empty {
+                // Someone could've been given data between the locks.
+                if users.get(&user).is_some_and(|x| x.read().expect("No panics").is_empty()) {users.remove(&user);}
+            }
+        }
+        if !changed.is_empty() {
+            self.changed_users.lock().expect("No panics").extend(changed);
+            self.changed.notify_one();
+        }
+        expired
+    }
+
+    /// When the next timed gag expires.
+    pub fn next_expiry(&self) -> Option<Timestamp> {
+        self.users.read().expect("No panics").values().filter_map(|x| x.read().expect("No panics").next_expiry()).min()
+    }
+
+    /// Every user with any data.
+    pub fn users(&self) -> HashSet<UserId> {
+        self.users.read().expect("No panics").keys().copied().collect()
+    }
+
+    /// Gets a copy of a user's [`UserState`].
     ///
-    /// Yes this does invalidate the entire point of both using [`RwLock`] and using multiple of them.
-    pub fn cleanup(&self, now: Timestamp) {
-        self.trusts.write().expect("No pancis").retain(|_, x| x != &GaggeeTrust::default());
-        let mut gags_lock = self.gags.write().expect("No panics");
-        for user_gags in gags_lock.values_mut() {user_gags.retain(|_, x| x.until.is_none_or(|until| now < until));}
-        gags_lock.retain(|_, x| x != &HashMap::<_, _>::default());
-        self.max_msg_lengths.write().expect("No panics").retain(|_, x| *x != default_max_msg_length());
-        self.safewords.write().expect("No panics").retain(|_, x| x != &Safewords::default());
-        self.gag_defaults.write().expect("No panics").retain(|_, x| x != &GagDefaults::default());
+    /// If the user has no data, returns the default.
+    pub fn user(&self, user: UserId) -> UserState {
+        self.with_user(user, UserState::clone)
+    }
+
+    /// Calls `f` with a user's [`UserState`] read locked.
+    ///
+    /// If the user has no data, `f` is given the default.
+    pub fn with_user<T>(&self, user: UserId, f: impl FnOnce(&UserState) -> T) -> T {
+        let x = self.users.read().expect("No panics").get(&user).cloned();
+        match x {
+            Some(x) => f(&x.read().expect("No panics")),
+            None => f(&UserState::default())
+        }
+    }
+
+    /// Calls `f` with a user's [`UserState`] write locked, adding the user if they have no data, and [marks them as changed](Self::mark_changed).
+    pub fn with_user_mut<T>(&self, user: UserId, f: impl FnOnce(&mut UserState) -> T) -> T {
+        match self.try_with_user_mut(user, |x| Ok::<_, std::convert::Infallible>(f(x))) {
+            Ok(ret) => ret,
+            Err(e) => match e {}
+        }
+    }
+
+    /// Like [`Self::with_user_mut`], but if `f` returns an [`Err`], the user isn't added or marked as changed.
+    ///
+    /// `f` must not change anything before returning an [`Err`].
+    pub fn try_with_user_mut<T, E>(&self, user: UserId, f: impl FnOnce(&mut UserState) -> Result<T, E>) -> Result<T, E> {
+        let existing = self.users.read().expect("No panics").get(&user).cloned();
+        let ret = match existing {
+            Some(x) => f(&mut x.write().expect("No panics")),
+            None => {
+                let mut users = self.users.write().expect("No panics");
+                match users.get(&user).cloned() {
+                    // Someone could've been given data between the locks.
+                    Some(x) => {
+                        drop(users);
+                        f(&mut x.write().expect("No panics"))
+                    },
+                    None => {
+                        let mut x = UserState::default();
+                        let ret = f(&mut x);
+                        if ret.is_ok() {users.insert(user, Arc::new(RwLock::new(x)));}
+                        ret
+                    }
+                }
+            }
+        };
+        if ret.is_ok() {self.mark_changed(user);}
+        ret
+    }
+
+    /// Marks a user's data as changed so the next save includes it.
+    pub fn mark_changed(&self, user: UserId) {
+        self.changed_users.lock().expect("No panics").insert(user);
+        self.changed.notify_one();
+    }
+
+    /// Returns and clears the users whose data changed since the last call.
+    pub fn take_changed_users(&self) -> HashSet<UserId> {
+        std::mem::take(&mut *self.changed_users.lock().expect("No panics"))
     }
 
     /// Tie a gaggee.
     pub fn tie(&self, gaggee: UserId, gagger: MemberId, new_tie: NewTie) -> Result<(), TieError> {
-        let trust = self.trust_for(gaggee, gagger);
+        self.try_with_user_mut(gaggee, |x| {
+            let trust = x.trust_for(gaggee, gagger);
 
-        if !trust.tie {Err(TieError::NoConsentForTie)?}
+            if !trust.tie {Err(TieError::NoConsentForTie)?}
+            if new_tie.keep_key && gaggee == gagger.user {Err(TieError::CantKeepOwnKey)?}
+            if new_tie.keep_key && !trust.untie          {Err(TieError::NoConsentForKey)?}
 
-        let mut lock = self.gags.write().expect("No panics");
-        let gag = lock.get_mut(&gaggee).ok_or(TieError::WasntGagged)?
-            .get_mut(&new_tie.channel).ok_or(TieError::WasntGagged)?;
+            let gag = x.gags.get_mut(&new_tie.scope).ok_or(TieError::WasntGagged)?;
 
-        if !trust.gag_modes.contains(&gag.config.mode) {Err(TieError::NoConsentForMode(gag.config.mode))?}
-        if gag.config.tie {Err(TieError::AlreadyTied)?}
+            if !trust.gag_modes.contains(&gag.config.mode) {Err(TieError::NoConsentForMode(gag.config.mode.clone()))?}
+            if gag.config.tie {Err(TieError::AlreadyTied)?}
 
-        gag.config.tie = true;
+            gag.config.tie = true;
+            if new_tie.keep_key {gag.key = Some(GagKey::new(gagger));}
 
-        Ok(())
+            Ok(())
+        })
     }
 
     /// Untie a gaggee.
     pub fn untie(&self, gaggee: UserId, gagger: MemberId, new_untie: NewUntie) -> Result<(), UntieError> {
-        let trust = self.trust_for(gaggee, gagger);
-
-        if !trust.untie {
-            if gaggee == gagger.user {
-                Err(UntieError::CantUntieYourself)?
-            } else {
-                Err(UntieError::NoConsentForUntie)?
+        self.try_with_user_mut(gaggee, |x| {
+            let trust = x.trust_for(gaggee, gagger);
+
+            if !trust.untie {
+                if gaggee == gagger.user {
+                    Err(UntieError::CantUntieYourself)?
+                } else {
+                    Err(UntieError::NoConsentForUntie)?
+                }
             }
-        }
 
-        let mut lock = self.gags.write().expect("No panics");
-        let gag = lock.get_mut(&gaggee).ok_or(UntieError::WasntGagged)?
-            .get_mut(&new_untie.channel).ok_or(UntieError::WasntGagged)?;
+            let gag = x.gags.get_mut(&new_untie.scope).ok_or(UntieError::WasntGagged)?;
 
-        if !trust.gag_modes.contains(&gag.config.mode) {Err(UntieError::NoConsentForMode(gag.config.mode))?}
-        if !gag.config.tie {Err(UntieError::WasntTied)?}
+            if !trust.gag_modes.contains(&gag.config.mode) {Err(UntieError::NoConsentForMode(gag.config.mode.clone()))?}
+            if !gag.config.tie {Err(UntieError::WasntTied)?}
+            if !gag.unlockable_by(gagger) {Err(UntieError::NotKeyholder)?}
 
-        gag.config.tie = false;
+            gag.config.tie = false;
+            gag.key = None;
 
-        Ok(())
+            Ok(())
+        })
+    }
+
+    /// Give the key of a tied gag to someone else.
+    ///
+    /// The new keyholder needs the gaggee's [`Trust::untie`] consent.
+    pub fn give_key(&self, gaggee: UserId, gagger: MemberId, give_key: GiveKey) -> Result<(), GiveKeyError> {
+        self.try_with_user_mut(gaggee, |x| {
+            if give_key.to.user == gaggee {Err(GiveKeyError::GaggeeCantHoldKey)?}
+
+            let trust = x.trust_for(gaggee, give_key.to);
+
+            let gag = x.gags.get_mut(&give_key.scope).ok_or(GiveKeyError::WasntGagged)?;
+            let key = gag.key.as_mut().ok_or(GiveKeyError::NoKey)?;
+
+            if key.holder != gagger {Err(GiveKeyError::NotKeyholder)?}
+            if !trust.untie {Err(GiveKeyError::NoConsentForUntie)?}
+            if !trust.gag_modes.contains(&gag.config.mode) {Err(GiveKeyError::NoConsentForMode(gag.config.mode.clone()))?}
+
+            key.give(give_key.to);
+
+            Ok(())
+        })
     }
 
-    /// Get the [`MessageAction`] to do for a [`Message`].
-    pub fn get_action(&self, msg: &Message) -> Option<MessageAction> {
+    /// Whether a user has any gags, so the [`GagLocation`] of their messages is worth finding.
+    pub fn has_gags(&self, user: UserId) -> bool {
+        self.with_user(user, |x| !x.gags.is_empty())
+    }
+
+    /// Get the [`MessageAction`] to do for a [`Message`] sent at `location`.
+    ///
+    /// Uses the most specific [`Gag`] that applies at `location`. If it's gagged, counts down the gag's [`Gag::messages_left`] and the gagged words of [`Gag::words_left`], removing it if either runs out.
+    ///
+    /// Only locks the author's [`UserState`], and only write locks it if the gag has a count to count down.
+    pub fn get_action(&self, msg: &Message, location: GagLocation) -> Option<MessageAction> {
         if matches!(msg.message_reference, Some(MessageReference {kind: MessageReferenceKind::Forward, ..})) {return None;}
-        let gags_lock = self.gags.read().expect("No panics");
-        let gag = gags_lock.get(&msg.author.id)?.get(&msg.channel_id)?;
-        if gag.until.is_none_or(|until| msg.timestamp <= until) && !self.safewords.read().expect("No panics").get(&msg.author.id).is_some_and(|safeword| safeword.is_safewording(msg.channel_id, msg.guild_id)) {
-            let max_msg_length = self.max_msg_lengths.read().expect("No panics").get(&msg.author.id).copied().unwrap_or(default_max_msg_length());
-            if msg.content.len() <= max_msg_length {
-                Some(MessageAction::Gag(gag.config.mode))
-            } else {
-                Some(MessageAction::WarnTooLong(max_msg_length))
+        let lock = self.users.read().expect("No panics").get(&msg.author.id)?.clone();
+        let scope = {
+            let x = lock.read().expect("No panics");
+            let (scope, gag) = x.gag_at(location, msg.timestamp)?;
+            if x.safewords.is_safewording(location) {return None;}
+            if msg.content.len() > x.max_msg_length() {return Some(MessageAction::WarnTooLong(x.max_msg_length()));}
+            if gag.messages_left.is_none() && gag.words_left.is_none() {
+                return Some(MessageAction::Gag {config: gag.config.clone(), preserve_markup: x.preserve_markup(), expired: None});
             }
-        } else {
-            None
-        }
+            scope
+        };
+
+        let mut lock = lock.write().expect("No panics");
+        let x = &mut *lock;
+        let preserve_markup = x.preserve_markup();
+        // The gag could've been removed or changed between the locks.
+        let gag = x.gags.get_mut(&scope)?;
+        let counted = gag.messages_left.is_some() || gag.words_left.is_some();
+        let expired = gag.count_down(gagged_word_count(&msg.content, preserve_markup)).then_some(ExpiredGag {user: msg.author.id, scope, announce: x.announce_expiry});
+        let action = MessageAction::Gag {
+            config: gag.config.clone(),
+            preserve_markup,
+            expired
+        };
+        if expired.is_some() {x.gags.remove(&scope);}
+        drop(lock);
+        if counted {self.mark_changed(msg.author.id);}
+        Some(action)
     }
 
     /// Gag a user.
     pub fn gag(&self, gaggee: UserId, gagger: MemberId, new_gag: NewGag) -> Result<(), GagError> {
-        let trust = self.trust_for(gaggee, gagger);
-
-        if !trust.gag {Err(GagError::NoConsentForGag)?}
-        if new_gag.gag.config.tie && !trust.tie  {Err(GagError::NoConsentForTie)?}
-        if !trust.gag_modes.contains(&new_gag.gag.config.mode) {Err(GagError::NoConsentForMode)?}
-
-        match self.gags.write().expect("No panics").entry(gaggee).or_default().entry(new_gag.channel) {
-            Entry::Occupied(_) => Err(GagError::AlreadyGagged)?,
-            Entry::Vacant(e) => {e.insert(new_gag.into());}
-        }
+        self.try_with_user_mut(gaggee, |x| {
+            let trust = x.trust_for(gaggee, gagger);
+
+            if !trust.gag {Err(GagError::NoConsentForGag)?}
+            if new_gag.gag.config.tie && !trust.tie  {Err(GagError::NoConsentForTie)?}
+            if new_gag.gag.key.is_some() && !new_gag.gag.config.tie {Err(GagError::KeyWithoutTie)?}
+            if new_gag.gag.key.is_some() && gaggee == gagger.user   {Err(GagError::CantKeepOwnKey)?}
+            if new_gag.gag.key.is_some() && !trust.untie            {Err(GagError::NoConsentForKey)?}
+            if !trust.gag_modes.contains(&new_gag.gag.config.mode) {Err(GagError::NoConsentForMode)?}
+            if let Some(filter) = new_gag.gag.config.filters.iter().find(|filter| !trust.gag_filters.contains(filter)) {Err(GagError::NoConsentForFilter(*filter))?}
+
+            match x.gags.entry(new_gag.scope) {
+                Entry::Occupied(_) => Err(GagError::AlreadyGagged)?,
+                Entry::Vacant(e) => {e.insert(new_gag.into());}
+            }
 
+            Ok(())
+        })?;
+        self.gags_changed.notify_one();
         Ok(())
     }
 
     /// Ungag a user.
     pub fn ungag(&self, gaggee: UserId, gagger: MemberId, new_ungag: NewUngag) -> Result<(), UngagError> {
-        let trust = self.trust_for(gaggee, gagger);
+        self.try_with_user_mut(gaggee, |x| {
+            let trust = x.trust_for(gaggee, gagger);
+
+            if !trust.ungag {Err(UngagError::NoConsentForUngag)?}
+
+            match x.gags.entry(new_ungag.scope) {
+                Entry::Occupied(gag) => {
+                    if gag.get().config.tie && !trust.untie {
+                        if gaggee == gagger.user {
+                            Err(UngagError::CantUntieYourself)?
+                        } else {
+                            Err(UngagError::NoConsentForUntie)?
+                        }
+                    }
+                    if gag.get().config.tie && !gag.get().unlockable_by(gagger) {Err(UngagError::NotKeyholder)?}
+                    if !trust.gag_modes.contains(&gag.get().config.mode) {Err(UngagError::NoConsentForMode(gag.get().config.mode.clone()))?}
+                    if let Some(filter) = gag.get().config.filters.iter().find(|filter| !trust.gag_filters.contains(filter)) {Err(UngagError::NoConsentForFilter(*filter))?}
 
-        if !trust.ungag {Err(UngagError::NoConsentForUngag)?}
+                    gag.remove();
+                },
+                Entry::Vacant(_) => Err(UngagError::WasntGagged)?
+            }
 
-        match self.gags.write().expect("No panics").get_mut(&gaggee).ok_or(UngagError::WasntGagged)?.entry(new_ungag.channel) {
-            Entry::Occupied(gag) => {
-                if gag.get().config.tie && !trust.untie {
-                    if gaggee == gagger.user {
-                        Err(UngagError::CantUntieYourself)?
-                    } else {
-                        Err(UngagError::NoConsentForUntie)?
-                    }
+            Ok(())
+        })
+    }
+
+    /// Change a gaggee's gag's mode, intensity, word shape, filters, and/or allowed words.
+    ///
+    /// Loosening a tied gag, by lowering its intensity, allowing new words, or changing its mode, word shape, or filters, needs [`Trust::untie`] consent and its key.
+    ///
+    /// Returns the old [`GagConfig`].
+    pub fn change_gag(&self, gaggee: UserId, gagger: MemberId, change_gag: ChangeGag) -> Result<GagConfig, ChangeGagError> {
+        self.try_with_user_mut(gaggee, |x| {
+            let trust = x.trust_for(gaggee, gagger);
+
+            if !trust.gag {Err(ChangeGagError::NoConsentForGag)?;}
+            if let Some(mode) = &change_gag.mode && !trust.gag_modes.contains(mode) {Err(ChangeGagError::NoConsentForMode(mode.clone()))?;}
+            if let Some(filter) = change_gag.filters.iter().flatten().find(|filter| !trust.gag_filters.contains(filter)) {Err(ChangeGagError::NoConsentForFilter(*filter))?;}
+
+            let gag = x.gags.get_mut(&change_gag.scope).ok_or(ChangeGagError::WasntGagged)?;
+
+            // Other modes, word shapes, and filters can all let more through, so any change to them counts.
+            let loosens = change_gag.intensity.is_some_and(|intensity| intensity < gag.config.intensity)
+                || change_gag.allowed_words.as_ref().is_some_and(|allowed_words| !allowed_words.is_subset(&gag.config.allowed_words))
+                || change_gag.mode       .as_ref().is_some_and(|mode      | *mode       != gag.config.mode      )
+                || change_gag.word_shape          .is_some_and(|word_shape| word_shape  != gag.config.word_shape)
+                || change_gag.filters    .as_ref().is_some_and(|filters   | *filters    != gag.config.filters   );
+            if gag.config.tie && !trust.untie && loosens {
+                if gaggee == gagger.user {
+                    Err(ChangeGagError::CantUntieYourself)?
+                } else {
+                    Err(ChangeGagError::NoConsentForUntie)?
                 }
-                if !trust.gag_modes.contains(&gag.get().config.mode) {Err(UngagError::NoConsentForMode(gag.get().config.mode))?}
+            }
+            if gag.config.tie && loosens && !gag.unlockable_by(gagger) {Err(ChangeGagError::NotKeyholder)?}
 
-                gag.remove();
-            },
-            Entry::Vacant(_) => Err(UngagError::WasntGagged)?
-        }
+            let old = gag.config.clone();
+            if let Some(mode) = change_gag.mode {gag.config.mode = mode;}
+            if let Some(intensity    ) = change_gag.intensity     {gag.config.intensity     = intensity    ;}
+            if let Some(word_shape   ) = change_gag.word_shape    {gag.config.word_shape    = word_shape   ;}
+            if let Some(filters      ) = change_gag.filters       {gag.config.filters       = filters      ;}
+            if let Some(allowed_words) = change_gag.allowed_words {gag.config.allowed_words = allowed_words;}
 
-        Ok(())
+            Ok(old)
+        })
     }
 
-    /// Change a gaggee's gag to `mode`.
+    /// Add time to or, if [`ChangeTimer::seconds`] is negative, remove time from a gaggee's timed gag.
     ///
-    /// Returns the old [`GagModeName`].
-    pub fn change_gag(&self, gaggee: UserId, gagger: MemberId, change_gag: ChangeGag) -> Result<GagModeName, ChangeGagError> {
-        let trust = self.trust_for(gaggee, gagger);
-
-        if !trust.gag {Err(ChangeGagError::NoConsentForGag)?;}
-        if !trust.gag_modes.contains(&change_gag.mode) {Err(ChangeGagError::NoConsentForMode(change_gag.mode))?;}
+    /// Adding time needs [`Trust::extend`] consent. Removing time needs [`Trust::shorten`] consent and, if the gag is tied, [`Trust::untie`] consent.
+    ///
+    /// Removing more time than is left makes the gag expire.
+    ///
+    /// Returns the new [`Gag::until`].
+    pub fn change_timer(&self, gaggee: UserId, gagger: MemberId, change_timer: ChangeTimer) -> Result<Timestamp, ChangeTimerError> {
+        let until = self.try_with_user_mut(gaggee, |x| {
+            let trust = x.trust_for(gaggee, gagger);
+            let shortens = change_timer.seconds < 0;
+
+            if !shortens && !trust.extend  {Err(ChangeTimerError::NoConsentForExtend )?}
+            if  shortens && !trust.shorten {Err(ChangeTimerError::NoConsentForShorten)?}
+
+            let gag = x.gags.get_mut(&change_timer.scope).ok_or(ChangeTimerError::WasntGagged)?;
+
+            if !trust.gag_modes.contains(&gag.config.mode) {Err(ChangeTimerError::NoConsentForMode(gag.config.mode.clone()))?}
+            if shortens && gag.config.tie && !trust.untie {
+                if gaggee == gagger.user {
+                    Err(ChangeTimerError::CantUntieYourself)?
+                } else {
+                    Err(ChangeTimerError::NoConsentForUntie)?
+                }
+            }
+            if shortens && gag.config.tie && !gag.unlockable_by(gagger) {Err(ChangeTimerError::NotKeyholder)?}
 
-        let old = match self.gags.write().expect("No panics").entry(gaggee).or_default().get_mut(&change_gag.channel) {
-            Some(gag) => {let old = gag.config.mode; gag.config.mode = change_gag.mode; old},
-            None => Err(ChangeGagError::WasntGagged)?
-        };
+            let until = add_seconds(gag.until.ok_or(ChangeTimerError::NoTimer)?, change_timer.seconds).map_err(|_| ChangeTimerError::TooLong)?;
+            gag.until = Some(until);
 
-        Ok(old)
+            Ok(until)
+        })?;
+        self.gags_changed.notify_one();
+        Ok(until)
     }
 
     /// Get a user's [`Trust`] for a member.
     pub fn trust_for(&self, gaggee: UserId, gagger: MemberId) -> Trust {
-        if gaggee == gagger.user {
-            Trust::for_self()
-        } else if let Some(gaggee_trust) = self.trusts.read().expect("No panics").get(&gaggee) {
-            let mut ret = gaggee_trust.global.clone();
-            if let Some(diff) = gaggee_trust.per_guild .get(&gagger.guild) {diff.apply(&mut ret);}
-            if let Some(diff) = gaggee_trust.per_user  .get(&gagger.user ) {diff.apply(&mut ret);}
-            if let Some(diff) = gaggee_trust.per_member.get(&gagger      ) {diff.apply(&mut ret);}
-            ret
-        } else {
-            Default::default()
-        }
+        self.with_user(gaggee, |x| x.trust_for(gaggee, gagger))
+    }
+
+    /// Get whether to keep Discord markup as is when gagging a user.
+    pub fn preserve_markup_for(&self, user: UserId) -> bool {
+        self.with_user(user, UserState::preserve_markup)
     }
 
     /// Export a user's data.
     pub fn export(&self, user: UserId) -> PortableGaggee {
-        PortableGaggee {
-            trusts        : self.trusts         .read().expect("No panics").get(&user).cloned(),
-            gags          : self.gags           .read().expect("No panics").get(&user).cloned(),
-            max_msg_length: self.max_msg_lengths.read().expect("No panics").get(&user).cloned(),
-            safewords     : self.safewords      .read().expect("No panics").get(&user).cloned(),
-            gag_defaults  : self.gag_defaults   .read().expect("No panics").get(&user).cloned()
-        }
+        self.user(user).into()
     }
 
     /// Import a user's data.
     pub fn import(&self, user: UserId, data: PortableGaggee) {
-        let PortableGaggee {trusts, gags, max_msg_length, safewords, gag_defaults} = data;
-        
-        match trusts {
-            Some(trusts) => {self.trusts.write().expect("No panics").insert(user, trusts);},
-            None         => {self.trusts.write().expect("No panics").remove(&user);}
-        }
-        match gags {
-            Some(gags) => {self.gags.write().expect("No panics").insert(user, gags);},
-            None       => {self.gags.write().expect("No panics").remove(&user);}
-        }
-        match max_msg_length {
-            Some(max_msg_length) => {self.max_msg_lengths.write().expect("No panics").insert(user, max_msg_length);},
-            None                 => {self.max_msg_lengths.write().expect("No panics").remove(&user);}
-        }
-        match safewords {
-            Some(safewords) => {self.safewords.write().expect("No panics").insert(user, safewords);},
-            None            => {self.safewords.write().expect("No panics").remove(&user);}
-        }
-        match gag_defaults {
-            Some(gag_defaults) => {self.gag_defaults.write().expect("No panics").insert(user, gag_defaults);},
-            None               => {self.gag_defaults.write().expect("No panics").remove(&user);}
-        }
+        self.with_user_mut(user, |x| *x = data.into());
+        self.gags_changed.notify_one();
     }
 }
 
+/// A gag removed by [`State::cleanup`] because it expired, or by [`State::get_action`] because it ran out of messages or words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpiredGag {
+    /// The gaggee.
+    pub user: UserId,
+    /// The [`GagScope`] the gag was in.
+    pub scope: GagScope,
+    /// [`UserState::announce_expiry`].
+    pub announce: bool
+}
+
 /// The default max length of a message to gag.
 ///
 /// Currently 256.
 pub fn default_max_msg_length() -> usize {
     256
 }
+
+/// The default for whether to keep Discord markup as is when gagging.
+///
+/// Currently [`true`].
+pub fn default_preserve_markup() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_change_nothing() {
+        let state = State::default();
+        let new_gag = || NewGag {scope: GagScope::Global, gag: Gag::default()};
+        assert!(matches!(state.gag(1.into(), MemberId {guild: 3.into(), user: 2.into()}, new_gag()), Err(GagError::NoConsentForGag)));
+        assert!(state.users().is_empty());
+        assert!(state.take_changed_users().is_empty());
+        assert!(state.gag(1.into(), MemberId {guild: 3.into(), user: 1.into()}, new_gag()).is_ok());
+        assert!(matches!(state.gag(1.into(), MemberId {guild: 3.into(), user: 1.into()}, new_gag()), Err(GagError::AlreadyGagged)));
+        assert_eq!(state.take_changed_users(), [1.into()].into());
+        assert!(matches!(state.untie(1.into(), MemberId {guild: 3.into(), user: 1.into()}, NewUntie {scope: GagScope::Global}), Err(UntieError::CantUntieYourself)));
+        assert!(state.take_changed_users().is_empty());
+    }
+
+    #[test]
+    fn keys() {
+        let state = State::default();
+        let (gaggee, tier, other) = (MemberId {guild: 3.into(), user: 1.into()}, MemberId {guild: 3.into(), user: 2.into()}, MemberId {guild: 3.into(), user: 4.into()});
+        let keyed = |holder| NewGag {scope: GagScope::Global, gag: Gag {key: Some(GagKey::new(holder)), config: GagConfig {tie: true, ..Default::default()}, ..Default::default()}};
+        state.with_user_mut(gaggee.user, |x| x.trusts.global = Trust {gag: true, tie: true, gag_modes: GagModeName::all(), ..Default::default()});
+        assert!(matches!(state.gag(gaggee.user, gaggee, keyed(gaggee)), Err(GagError::CantKeepOwnKey)));
+        assert!(matches!(state.gag(gaggee.user, tier  , keyed(tier  )), Err(GagError::NoConsentForKey)));
+        state.with_user_mut(gaggee.user, |x| x.trusts.global.untie = true);
+        assert!(state.gag(gaggee.user, tier, keyed(tier)).is_ok());
+        assert!(matches!(state.untie(gaggee.user, other, NewUntie {scope: GagScope::Global}), Err(UntieError::NotKeyholder)));
+        assert!(matches!(state.untie(gaggee.user, MemberId {guild: 5.into(), ..tier}, NewUntie {scope: GagScope::Global}), Err(UntieError::NotKeyholder)));
+        assert!(state.give_key(gaggee.user, tier, GiveKey {scope: GagScope::Global, to: other}).is_ok());
+        assert!(matches!(state.untie(gaggee.user, tier, NewUntie {scope: GagScope::Global}), Err(UntieError::NotKeyholder)));
+        assert!(state.untie(gaggee.user, other, NewUntie {scope: GagScope::Global}).is_ok());
+    }
+}
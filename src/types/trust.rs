@@ -40,9 +40,18 @@ pub struct Trust {
     /// Can untie.
     #[serde(default, skip_serializing_if = "is_default")]
     pub untie: bool,
+    /// Can add time to timed gags.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub extend: bool,
+    /// Can remove time from timed gags. Shortening a tied gag also needs [`Self::untie`].
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub shorten: bool,
     /// Can gag/ungag for these [`GagMode`]s.
     #[serde(default, skip_serializing_if = "is_default")]
-    pub gag_modes: HashSet<GagModeName>
+    pub gag_modes: HashSet<GagModeName>,
+    /// Can gag/ungag with these [`GagFilter`]s.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub gag_filters: HashSet<GagFilter>
 }
 
 impl Trust {
@@ -55,7 +64,10 @@ impl Trust {
             ungag: true,
             tie: true,
             untie: false,
-            gag_modes: GagModeName::all()
+            extend: true,
+            shorten: true,
+            gag_modes: GagModeName::all(),
+            gag_filters: GagFilter::ALL.into()
         }
     }
 }
@@ -75,12 +87,24 @@ pub struct TrustDiff {
     /// If [`Some`], overwrites [`Trusr::untie`].
     #[serde(default, skip_serializing_if = "is_default")]
     pub untie: Option<bool>,
+    /// If [`Some`], overwrites [`Trust::extend`].
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub extend: Option<bool>,
+    /// If [`Some`], overwrites [`Trust::shorten`].
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub shorten: Option<bool>,
     /// Adds a [`GagMode`] to the allow list.
     #[serde(default, skip_serializing_if = "is_default")]
     pub allow_gag_modes: HashSet<GagModeName>,
     /// Removes a [`GagMode`] from the allow list.
     #[serde(default, skip_serializing_if = "is_default")]
-    pub disallow_gag_modes: HashSet<GagModeName>
+    pub disallow_gag_modes: HashSet<GagModeName>,
+    /// Adds a [`GagFilter`] to the allow list.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub allow_gag_filters: HashSet<GagFilter>,
+    /// Removes a [`GagFilter`] from the allow list.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub disallow_gag_filters: HashSet<GagFilter>
 }
 
 impl TrustDiff {
@@ -90,7 +114,11 @@ impl TrustDiff {
         if let Some(x) = self.ungag {to.ungag = x;}
         if let Some(x) = self.tie   {to.tie   = x;}
         if let Some(x) = self.untie {to.untie = x;}
-        for mode in &self.allow_gag_modes    {to.gag_modes.insert(*mode);}
+        if let Some(x) = self.extend  {to.extend  = x;}
+        if let Some(x) = self.shorten {to.shorten = x;}
+        for mode in &self.allow_gag_modes    {to.gag_modes.insert(mode.clone());}
         for mode in &self.disallow_gag_modes {to.gag_modes.remove( mode);}
+        for filter in &self.allow_gag_filters    {to.gag_filters.insert(*filter);}
+        for filter in &self.disallow_gag_filters {to.gag_filters.remove( filter);}
     }
 }
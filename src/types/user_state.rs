@@ -0,0 +1,211 @@
+//! Everything the bot knows about a single user.
+
+use std::collections::{HashMap, HashSet};
+
+use serenity::model::id::{UserId, ChannelId};
+use serenity::model::timestamp::Timestamp;
+
+use thiserror::Error;
+
+use crate::types::*;
+
+/// Everything the bot knows about a single user.
+///
+/// Kept behind its own lock in [`State::users`] so reading or changing one user doesn't block any others.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserState {
+    /// The user's [`GaggeeTrust`].
+    pub trusts: GaggeeTrust,
+    /// The user's [`Gag`]s in each [`GagScope`].
+    pub gags: HashMap<GagScope, Gag>,
+    /// The max length of a message to gag. If [`None`], [`default_max_msg_length`] is used.
+    pub max_msg_length: Option<usize>,
+    /// Whether to keep Discord markup like mentions and links as is when gagging. If [`None`], [`default_preserve_markup`] is used.
+    pub preserve_markup: Option<bool>,
+    /// The user's [`Safewords`].
+    pub safewords: Safewords,
+    /// The user's [`GagDefaults`].
+    pub gag_defaults: GagDefaults,
+    /// If [`true`], post a message when one of the user's gags expires or runs out of messages or words.
+    pub announce_expiry: bool,
+    /// Threads and forum posts where the user's gags on the channel they're in don't apply.
+    pub thread_opt_outs: HashSet<ChannelId>
+}
+
+/// The errors [`UserState::set_thread_inherits_gags`] can return.
+#[derive(Debug, Error)]
+pub enum ThreadOptOutError {
+    /// Tried to set whether a channel that isn't a thread or forum post inherits gags.
+    #[error("Tried to set whether a channel that isn't a thread or forum post inherits gags.")]
+    NotInThread,
+    /// Tried to opt a thread out of a tied gag without consent to untie it.
+    #[error("Tried to opt a thread out of a tied gag without consent to untie it.")]
+    NoConsentForUntie
+}
+
+impl UserState {
+    /// Get the user's [`Trust`] for a member.
+    pub fn trust_for(&self, user: UserId, gagger: MemberId) -> Trust {
+        if user == gagger.user {
+            Trust::for_self()
+        } else {
+            let mut ret = self.trusts.global.clone();
+            if let Some(diff) = self.trusts.per_guild .get(&gagger.guild) {diff.apply(&mut ret);}
+            if let Some(diff) = self.trusts.per_user  .get(&gagger.user ) {diff.apply(&mut ret);}
+            if let Some(diff) = self.trusts.per_member.get(&gagger      ) {diff.apply(&mut ret);}
+            ret
+        }
+    }
+
+    /// The max length of a message to gag.
+    pub fn max_msg_length(&self) -> usize {
+        self.max_msg_length.unwrap_or(default_max_msg_length())
+    }
+
+    /// Whether to keep Discord markup as is when gagging.
+    pub fn preserve_markup(&self) -> bool {
+        self.preserve_markup.unwrap_or(default_preserve_markup())
+    }
+
+    /// Removes gags that expired by `now`.
+    ///
+    /// Returns the [`GagScope`]s they were in.
+    pub fn expire_gags(&mut self, now: Timestamp) -> Vec<GagScope> {
+        let expired = self.gags.iter().filter(|(_, gag)| gag.until.is_some_and(|until| until <= now)).map(|(scope, _)| *scope).collect::<Vec<_>>();
+        for scope in &expired {self.gags.remove(scope);}
+        expired
+    }
+
+    /// Sets whether the user's gags on the channel a thread is in apply in the thread, by adding or removing it from [`Self::thread_opt_outs`].
+    ///
+    /// Opting out of a tied gag needs `caller` to have [`Trust::untie`] consent.
+    pub fn set_thread_inherits_gags(&mut self, user: UserId, caller: MemberId, location: GagLocation, enabled: bool) -> Result<(), ThreadOptOutError> {
+        let parent = location.parent.ok_or(ThreadOptOutError::NotInThread)?;
+        if enabled {
+            self.thread_opt_outs.remove(&location.channel);
+            return Ok(());
+        }
+        if self.gags.get(&GagScope::Channel(parent)).is_some_and(|gag| gag.config.tie) && !self.trust_for(user, caller).untie {Err(ThreadOptOutError::NoConsentForUntie)?}
+        self.thread_opt_outs.insert(location.channel);
+        Ok(())
+    }
+
+    /// The unexpired [`Gag`] that applies at `location`, if any, and its [`GagScope`].
+    ///
+    /// If there are several, the most specific one is used.
+    ///
+    /// In threads in [`Self::thread_opt_outs`], untied gags on the thread's channel are skipped.
+    pub fn gag_at(&self, location: GagLocation, now: Timestamp) -> Option<(GagScope, &Gag)> {
+        let opted_out = location.parent.filter(|_| self.thread_opt_outs.contains(&location.channel)).map(GagScope::Channel);
+        location.scopes()
+            .filter(|scope| Some(*scope) != opted_out || self.gags.get(scope).is_some_and(|gag| gag.config.tie))
+            .filter_map(|scope| Some((scope, self.gags.get(&scope)?)))
+            .find(|(_, gag)| gag.until.is_none_or(|until| now <= until))
+    }
+
+    /// When the user's next timed gag expires.
+    pub fn next_expiry(&self) -> Option<Timestamp> {
+        self.gags.values().filter_map(|gag| gag.until).min()
+    }
+
+    /// Whether [`Self::expire_gags`] or [`Self::cleanup`] would change anything, or the user [is empty](Self::is_empty).
+    pub fn needs_cleanup(&self, now: Timestamp) -> bool {
+        self.gags.values().any(|gag| gag.until.is_some_and(|until| until <= now))
+            || self.max_msg_length  == Some(default_max_msg_length ())
+            || self.preserve_markup == Some(default_preserve_markup())
+            || self.is_empty()
+    }
+
+    /// Removes settings that are the same as their defaults.
+    ///
+    /// Returns [`true`] if anything changed.
+    pub fn cleanup(&mut self) -> bool {
+        let mut changed = false;
+        if self.max_msg_length  == Some(default_max_msg_length ()) {self.max_msg_length  = None; changed = true;}
+        if self.preserve_markup == Some(default_preserve_markup()) {self.preserve_markup = None; changed = true;}
+        changed
+    }
+
+    /// Whether the user has no data, so can be forgotten.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl From<UserState> for PortableGaggee {
+    fn from(value: UserState) -> Self {
+        Self {
+            trusts         : Some(value.trusts      ).filter(|x| x != &GaggeeTrust::default()),
+            gags           : Some(value.gags        ).filter(|x| !x.is_empty()),
+            max_msg_length : value.max_msg_length,
+            preserve_markup: value.preserve_markup,
+            safewords      : Some(value.safewords   ).filter(|x| x != &Safewords::default()),
+            gag_defaults   : Some(value.gag_defaults).filter(|x| x != &GagDefaults::default()),
+            announce_expiry: Some(value.announce_expiry).filter(|x| *x),
+            thread_opt_outs: Some(value.thread_opt_outs).filter(|x| !x.is_empty())
+        }
+    }
+}
+
+impl From<PortableGaggee> for UserState {
+    fn from(value: PortableGaggee) -> Self {
+        Self {
+            trusts         : value.trusts      .unwrap_or_default(),
+            gags           : value.gags        .unwrap_or_default(),
+            max_msg_length : value.max_msg_length,
+            preserve_markup: value.preserve_markup,
+            safewords      : value.safewords   .unwrap_or_default(),
+            gag_defaults   : value.gag_defaults.unwrap_or_default(),
+            announce_expiry: value.announce_expiry.unwrap_or_default(),
+            thread_opt_outs: value.thread_opt_outs.unwrap_or_default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The time `secs` seconds after the unix epoch.
+    fn time(secs: i64) -> Timestamp {
+        Timestamp::from_unix_timestamp(secs).expect("The test time to be valid")
+    }
+
+    #[test]
+    fn expiry() {
+        let mut x = UserState::default();
+        x.gags.insert(GagScope::Channel(1.into()), Gag {until: Some(time(100)), ..Default::default()});
+        x.gags.insert(GagScope::Server (2.into()), Gag {until: Some(time(200)), ..Default::default()});
+        x.gags.insert(GagScope::Global           , Gag::default());
+        assert_eq!(x.next_expiry(), Some(time(100)));
+        assert!(!x.needs_cleanup(time(99)));
+        assert!( x.needs_cleanup(time(100)));
+        assert_eq!(x.expire_gags(time(99)), []);
+        assert_eq!(x.expire_gags(time(100)), [GagScope::Channel(1.into())]);
+        assert_eq!(x.next_expiry(), Some(time(200)));
+        assert_eq!(x.expire_gags(time(1000)), [GagScope::Server(2.into())]);
+        assert_eq!(x.next_expiry(), None);
+        assert_eq!(x.gags.len(), 1);
+    }
+
+    #[test]
+    fn threads() {
+        let mut x = UserState::default();
+        let me = MemberId {guild: 3.into(), user: 4.into()};
+        x.gags.insert(GagScope::Channel(1.into()), Gag::default());
+        let thread = GagLocation {channel: 2.into(), parent: Some(1.into()), category: None, server: Some(3.into())};
+        assert_eq!(x.gag_at(thread, time(0)).map(|(scope, _)| scope), Some(GagScope::Channel(1.into())));
+        assert!(x.set_thread_inherits_gags(me.user, me, thread, false).is_ok());
+        assert_eq!(x.gag_at(thread, time(0)), None);
+        x.gags.insert(GagScope::Server(3.into()), Gag::default());
+        assert_eq!(x.gag_at(thread, time(0)).map(|(scope, _)| scope), Some(GagScope::Server(3.into())));
+
+        let mut x = UserState::default();
+        x.gags.insert(GagScope::Channel(1.into()), Gag {config: GagConfig {tie: true, ..Default::default()}, ..Default::default()});
+        assert!(matches!(x.set_thread_inherits_gags(me.user, me, thread, false), Err(ThreadOptOutError::NoConsentForUntie)));
+        assert!(x.thread_opt_outs.is_empty());
+        // Opting out before the gag was tied doesn't help either.
+        x.thread_opt_outs.insert(2.into());
+        assert_eq!(x.gag_at(thread, time(0)).map(|(scope, _)| scope), Some(GagScope::Channel(1.into())));
+    }
+}
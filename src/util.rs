@@ -1,20 +1,36 @@
 //! Common and generic utility stuff.
 
 use std::str::FromStr;
-use std::sync::LazyLock;
-use std::collections::HashSet;
+use std::collections::{HashSet, BTreeSet};
+use std::fmt::Display;
+use std::hash::Hash;
 
-use regex::Regex;
 use serenity::model::user::User;
+use serenity::model::id::{GuildId, ChannelId};
+use serenity::model::timestamp::Timestamp;
+use serenity::builder::AutocompleteChoice;
+use serenity::cache::Cache;
+use serenity::http::CacheHttp;
+use serenity::model::channel::Channel;
 
 use crate::types::*;
+use crate::markup::{self, Segment};
 
 /// Returns [`true`] if `x` is [`T::default`].
 pub fn is_default<T: Default + Eq>(x: &T) -> bool {x == &T::default()}
 
 /// Returns auto-...continuations?... for a comma separated list of [`GagModeName`]s.
-pub async fn csv_gag_mode_name_autocomplete<'a>(_: poise::Context<'_, crate::types::State, serenity::Error>, value: &'a str) -> Box<dyn Iterator<Item = String> + 'a + Send> {
-    let mut rets = GagModeName::all();
+pub async fn csv_gag_mode_name_autocomplete<'a>(_: poise::Context<'_, std::sync::Arc<crate::types::State>, serenity::Error>, value: &'a str) -> Box<dyn Iterator<Item = String> + 'a + Send> {
+    csv_autocomplete(GagModeName::all(), value)
+}
+
+/// Returns autocompletions for a comma separated list of [`GagFilter`]s.
+pub async fn csv_gag_filter_autocomplete<'a>(_: poise::Context<'_, std::sync::Arc<crate::types::State>, serenity::Error>, value: &'a str) -> Box<dyn Iterator<Item = String> + 'a + Send> {
+    csv_autocomplete(GagFilter::ALL.into(), value)
+}
+
+/// Returns autocompletions for a comma separated list of `T`s that aren't already in the list.
+fn csv_autocomplete<'a, T: FromStr + Eq + Hash + Display + Clone + Send + 'a>(mut rets: HashSet<T>, value: &'a str) -> Box<dyn Iterator<Item = String> + 'a + Send> {
     for x in value.split(',') {if let Ok(x) = FromStr::from_str(x) {rets.remove(&x);}}
     if value.ends_with(',') {
         Box::new(rets.into_iter().map(move |x| format!("{value}{x}")))
@@ -32,16 +48,37 @@ pub async fn csv_gag_mode_name_autocomplete<'a>(_: poise::Context<'_, crate::typ
     }
 }
 
-/// The [`Regex`] of message starts to keep at the start.
-static PREFIXES: LazyLock<Regex> = LazyLock::new(|| Regex::new("^(-#|#{1,3}) ").expect("The PREFIXES regex to be valid"));
+/// Returns autocompletions for a single [`GagModeName`], labeled with their icons and display names.
+pub async fn gag_mode_name_autocomplete<'a>(_: poise::Context<'_, std::sync::Arc<crate::types::State>, serenity::Error>, value: &'a str) -> impl Iterator<Item = AutocompleteChoice> + 'a {
+    let mut names = GagModeName::all().into_iter().filter(move |x| x.0.to_lowercase().starts_with(&value.to_lowercase())).collect::<Vec<_>>();
+    names.sort();
+    names.into_iter().map(|x| AutocompleteChoice::new(format!("{} {}", x.icon(), x.display_name()), x.0))
+}
 
 /// Convenience function to gag and format a message.
-pub fn to_gagged_message(text: &str, mode: GagModeName, author: &User) -> String {
-    let prefix = PREFIXES.find(text).filter(|x| x.start() == 0).map(|x| x.as_str()).unwrap_or_default();
-    format!("{prefix}{author} ({}): {}",
-        mode.icon(),
-        mode.get().rewrite(text.strip_prefix(prefix).expect("The message to always start with its prefix")).expect("The GagMode to be valid")
-    )
+///
+/// If [`GagConfig::mode`] isn't registered, uses [`GagModeName::default`].
+///
+/// Markdown formatting is always kept. If `preserve_markup` is [`true`], mentions, custom emoji, timestamps, links, and code are also kept.
+///
+/// If [`GagConfig::filters`] has [`GagFilter::Whisper`], the whole message is made subtext.
+/// # Errors
+/// If the gag mode fails to rewrite part of the message, returns that [`GagModeError`].
+pub fn to_gagged_message(text: &str, config: &GagConfig, preserve_markup: bool, author: &User) -> Result<String, GagModeError> {
+    let default_mode = GagModeName::default();
+    let mode = if config.mode.get().is_some() {&config.mode} else {&default_mode};
+    let gag_mode = mode.get().expect("The default GagModeName to always be registered");
+    let mut segments = markup::segments(text, preserve_markup).into_iter().peekable();
+    let prefix = segments.next_if(|segment| matches!(segment, Segment::LinePrefix(_))).map(|segment| segment.as_str()).unwrap_or_default();
+    let gagged = segments.map(|segment| match segment {
+        Segment::Gag(gag) => gag_mode.rewrite(gag, config.rewrite_options()),
+        _ => Ok(segment.as_str().to_string())
+    }).collect::<Result<String, _>>()?;
+    let message = format!("{prefix}{author} ({}): {gagged}", mode.icon());
+    Ok(match config.filters.contains(&GagFilter::Whisper) {
+        true  => markup::subtext(&message),
+        false => message
+    })
 }
 
 /// Parses a comma separated list of [`GagModeName`]s into a [`HashSet`]/
@@ -53,3 +90,97 @@ pub fn parse_csv_gag_modes(modes: Option<&str>) -> Result<HashSet<GagModeName>,
         None => Ok(Default::default())
     }
 }
+
+/// Parses a comma separated list of [`GagFilter`]s into any collection, keeping their order.
+///
+/// An empty string gives an empty collection.
+/// # Errors
+/// If a call to [`GagFilter::from_str`] returns an error, the substring that caused the error is returned.
+pub fn parse_csv_gag_filters<T: FromIterator<GagFilter>>(filters: &str) -> Result<T, &str> {
+    filters.split(',').filter(|x| !x.is_empty()).map(|x| GagFilter::from_str(x).map_err(|_| x)).collect()
+}
+
+/// Parses the `allow_gag_filters` and `disallow_gag_filters` options of the `/trust` commands with [`parse_csv_gag_filters`].
+///
+/// Omitted options give empty collections.
+/// # Errors
+/// If either has a substring that isn't a [`GagFilter`], returns the message to reply with.
+pub fn parse_trust_gag_filters<T: FromIterator<GagFilter>>(allow: Option<&str>, disallow: Option<&str>) -> Result<(T, T), String> {
+    let error = |bad_name| format!("Error! `{bad_name}` isn't a known gag filter");
+    Ok((
+        parse_csv_gag_filters(allow   .unwrap_or_default()).map_err(error)?,
+        parse_csv_gag_filters(disallow.unwrap_or_default()).map_err(error)?
+    ))
+}
+
+/// Parses a comma separated list of words into a [`BTreeSet`] of lowercase words for [`GagConfig::allowed_words`].
+///
+/// Spaces around words are ignored and an empty string gives an empty set.
+/// # Errors
+/// If a substring isn't a single [`WORD`], that substring is returned.
+pub fn parse_csv_words(words: &str) -> Result<BTreeSet<String>, &str> {
+    words.split(',').map(str::trim).filter(|x| !x.is_empty())
+        .map(|x| match WORD.find(x) {
+            Some(found) if found.as_str() == x => Ok(x.to_lowercase()),
+            _ => Err(x)
+        })
+        .collect()
+}
+
+/// Formats a set of words like `please, red`.
+pub fn format_words(words: &BTreeSet<String>) -> String {
+    words.iter().map(String::as_str).collect::<Vec<_>>().join(", ")
+}
+
+/// Formats a list of [`GagFilter`]s like `Stutter, Lisp`.
+pub fn format_gag_filters(filters: &[GagFilter]) -> String {
+    filters.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// Formats `timestamp` so Discord shows it in each user's timezone, like `<t:1700000000:f> (<t:1700000000:R>)`.
+pub fn format_timestamp(timestamp: Timestamp) -> String {
+    format!("<t:{0}:f> (<t:{0}:R>)", timestamp.unix_timestamp())
+}
+
+/// The [`GagLocation`] of `channel`, using the cache to find the channel a thread or forum post is in and the category.
+///
+/// If `channel` is in a server but isn't cached, it's fetched. If that fails, it's treated as a channel that isn't in a category.
+pub async fn gag_location(cache_http: impl CacheHttp, channel: ChannelId, server: Option<GuildId>) -> GagLocation {
+    let Some(server) = server else {return GagLocation {channel, parent: None, category: None, server}};
+    if let Some(location) = cache_http.cache().and_then(|cache| cached_gag_location(cache, channel, server)) {return location;}
+    match channel.to_channel(&cache_http).await {
+        Ok(Channel::Guild(guild_channel)) if guild_channel.thread_metadata.is_some() => GagLocation {
+            channel,
+            parent: guild_channel.parent_id,
+            category: guild_channel.parent_id.and_then(|parent| cache_http.cache()?.guild(server)?.channels.get(&parent)?.parent_id),
+            server: Some(server)
+        },
+        Ok(Channel::Guild(guild_channel)) => GagLocation {channel, parent: None, category: guild_channel.parent_id, server: Some(server)},
+        _ => GagLocation {channel, parent: None, category: None, server: Some(server)}
+    }
+}
+
+/// The [`GagLocation`] of `channel` if it and its server are in `cache`.
+fn cached_gag_location(cache: &Cache, channel: ChannelId, server: GuildId) -> Option<GagLocation> {
+    let guild = cache.guild(server)?;
+    Some(match guild.threads.iter().find(|thread| thread.id == channel) {
+        Some(thread) => GagLocation {
+            channel,
+            parent: thread.parent_id,
+            category: thread.parent_id.and_then(|parent| guild.channels.get(&parent)?.parent_id),
+            server: Some(server)
+        },
+        None => GagLocation {channel, parent: None, category: guild.channels.get(&channel)?.parent_id, server: Some(server)}
+    })
+}
+
+/// The [`GagScope`] of kind `kind` where `ctx` was invoked.
+///
+/// If there isn't one, tells the invoker why and returns [`None`].
+pub async fn gag_scope_of(ctx: &poise::Context<'_, std::sync::Arc<crate::types::State>, serenity::Error>, kind: GagScopeKind) -> Result<Option<GagScope>, serenity::Error> {
+    match gag_location(ctx, ctx.channel_id(), ctx.guild_id()).await.scope(kind) {
+        Ok(scope) => Ok(Some(scope)),
+        Err(GagScopeError::NotInCategory) => {ctx.say("Error! This channel isn't in a category").await?; Ok(None)},
+        Err(GagScopeError::NotInServer  ) => {ctx.say("Error! This channel isn't in a server"  ).await?; Ok(None)}
+    }
+}